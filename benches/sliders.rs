@@ -0,0 +1,64 @@
+//! Times the slider move generators, which share the occupancy between
+//! the pieces of a generator and between the two lookups of a queen.
+//! Run with `cargo bench --bench sliders`, and compare with the PEXT
+//! lookups of `cargo bench --bench sliders --features bmi2`.
+use chessmg::{Board, MoveGen, pext_available};
+use std::hint::black_box;
+use std::time::Instant;
+
+/// Middlegame positions, with sliders of both colors
+const FENS: [&str; 3] = [
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+const ITERATIONS: u32 = 1_000_000;
+
+/// Depth of the perft run on the first position, which is mostly slider
+/// lookups once the moves are played
+const PERFT_DEPTH: u32 = 4;
+
+/// Generates the moves of one kind of slider
+type Generator = fn(&mut MoveGen);
+
+fn main() {
+    let lookup = if pext_available() { "pext" } else { "magic" };
+    println!("slider lookups: {lookup}");
+
+    let boards: Vec<Board> = FENS
+        .iter()
+        .map(|fen| Board::from_fen(fen).unwrap())
+        .collect();
+    let generators: [(&str, Generator); 3] = [
+        ("bishops", |mg| mg.gen_white_bishop_moves()),
+        ("rooks", |mg| mg.gen_white_rook_moves()),
+        ("queens", |mg| mg.gen_white_queen_moves()),
+    ];
+    for (name, generate) in generators {
+        let mut move_gens: Vec<MoveGen> = boards.iter().map(MoveGen::new).collect();
+        let mut moves = 0;
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for mg in &mut move_gens {
+                mg.pseudo_move_list.clear();
+                generate(black_box(mg));
+                moves += mg.pseudo_move_list.len();
+            }
+        }
+        let elapsed = start.elapsed();
+        let calls = ITERATIONS * u32::try_from(FENS.len()).unwrap();
+        println!(
+            "{name:>8}: {:>6.1} ns per call ({moves} moves)",
+            elapsed.as_secs_f64() * 1e9 / f64::from(calls)
+        );
+    }
+
+    let start = Instant::now();
+    let nodes = black_box(&boards[0]).perft(PERFT_DEPTH);
+    let elapsed = start.elapsed();
+    println!(
+        "   perft: {:>6.1} ms at depth {PERFT_DEPTH} ({nodes} nodes)",
+        elapsed.as_secs_f64() * 1e3
+    );
+}
@@ -2,11 +2,14 @@
 //! representing the position of a kind of piece on a chessboard.
 //! Bitboards provide an efficient way to represent and manipulate chess positions
 //! through bitwise operations.
+use crate::utils::Square;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::ops::{BitAnd, BitOr, BitXor, Mul, Not, Shl, Shr};
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Mul, Not, Shl, Shr,
+};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 /// A `Bitboard` is a 64-bit integer where each bit represents the presence or absence
 /// of a piece on a chessboard square.
 ///
@@ -67,6 +70,24 @@ impl BitXor for Bitboard {
     }
 }
 
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+
 impl Not for Bitboard {
     type Output = Bitboard;
 
@@ -109,11 +130,34 @@ impl PartialEq<Bitboard> for u64 {
     }
 }
 
+impl From<u64> for Bitboard {
+    fn from(value: u64) -> Self {
+        Bitboard(value)
+    }
+}
+
+impl From<Bitboard> for u64 {
+    fn from(value: Bitboard) -> Self {
+        value.0
+    }
+}
+
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Square>>(squares: I) -> Self {
+        Bitboard(
+            squares
+                .into_iter()
+                .fold(0, |bits, square| bits | 1 << square as u8),
+        )
+    }
+}
+
 impl Mul<u64> for Bitboard {
     type Output = u64;
 
+    // Wrapping, as the magic multiplications overflow by design
     fn mul(self, rhs: u64) -> Self::Output {
-        self.0 * rhs
+        self.0.wrapping_mul(rhs)
     }
 }
 
@@ -134,6 +178,28 @@ impl Bitboard {
         self.0.wrapping_mul(n)
     }
 
+    /// Shifts towards h8 for a positive `offset`, towards a1 otherwise.
+    pub fn shift_signed(self, offset: i8) -> Bitboard {
+        let amount = usize::from(offset.unsigned_abs());
+        if offset >= 0 {
+            self << amount
+        } else {
+            self >> amount
+        }
+    }
+
+    /// The bitboard with the bits of `squares` set
+    pub fn from_squares(squares: &[Square]) -> Bitboard {
+        squares.iter().copied().collect()
+    }
+
+    /// The squares of the set bits, from a1 to h8, without modifying the
+    /// bitboard.
+    pub fn iter_squares(self) -> impl Iterator<Item = Square> {
+        let mut bitboard = self;
+        std::iter::from_fn(move || bitboard.pop_lsb().map(Square::from_usize))
+    }
+
     /// Finds the first set bit (least significant bit) in the bitboard,
     /// removing it from the bitboard, and returning its index.
     pub fn pop_lsb(&mut self) -> Option<usize> {
@@ -146,3 +212,69 @@ impl Bitboard {
         Some(lsb_index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u64_conversions() {
+        let bb = Bitboard::from(0xFF00);
+        assert_eq!(bb, Bitboard(0xFF00));
+        assert_eq!(u64::from(bb), 0xFF00);
+
+        let raw: u64 = Bitboard(0x8000_0000_0000_0001).into();
+        assert_eq!(raw, 0x8000_0000_0000_0001);
+    }
+
+    #[test]
+    fn test_iter_squares() {
+        let bb = Bitboard(1 << Square::H8 as u64 | 1 << Square::A1 as u64 | 1 << Square::D4 as u64);
+        let squares: Vec<Square> = bb.iter_squares().collect();
+        assert_eq!(squares, [Square::A1, Square::D4, Square::H8]);
+        assert_eq!(bb.count_ones(), 3);
+        assert_eq!(Bitboard(0).iter_squares().count(), 0);
+    }
+
+    #[test]
+    fn test_default_is_empty() {
+        assert_eq!(Bitboard::default(), Bitboard(0));
+        assert_eq!(Bitboard::default().count_ones(), 0);
+    }
+
+    #[test]
+    fn test_mul_wraps() {
+        let bitboard = Bitboard(0xFF00_0000_0000_0001);
+        let magic = 0x0080_1000_2040_8001;
+        assert_eq!(bitboard * magic, bitboard.wrapping_mul(magic));
+        assert_eq!(
+            bitboard * magic,
+            0xFF00_0000_0000_0001u64.wrapping_mul(magic)
+        );
+    }
+
+    #[test]
+    fn test_from_squares() {
+        let bitboard = Bitboard::from_squares(&[Square::A1, Square::H8]);
+        assert_eq!(u64::from(bitboard), 0x8000_0000_0000_0001);
+        assert!(Bitboard::from_squares(&[]) == 0);
+
+        let collected: Bitboard = bitboard.iter_squares().collect();
+        assert!(collected == bitboard);
+        let squares: Vec<Square> = Bitboard(0x0000_0010_0800_0000).iter_squares().collect();
+        assert!(Bitboard::from_squares(&squares) == 0x0000_0010_0800_0000);
+    }
+
+    #[test]
+    fn test_assign_operators() {
+        let mut bitboard = Bitboard(0b1100);
+        bitboard &= Bitboard(0b1010);
+        assert_eq!(u64::from(bitboard), 0b1000);
+        bitboard |= Bitboard(0b0011);
+        assert_eq!(u64::from(bitboard), 0b1011);
+        bitboard ^= Bitboard(0b1110);
+        assert_eq!(u64::from(bitboard), 0b0101);
+        bitboard ^= Bitboard(0b0101);
+        assert!(bitboard == 0);
+    }
+}
@@ -1,13 +1,21 @@
 use crate::bitboard::Bitboard;
 use crate::errors::ChessMgError;
-use crate::errors::ChessMgError::InvalidFEN;
-use crate::move_gen::{Move, MoveGen, Undo};
+use crate::errors::ChessMgError::{InvalidFEN, InvalidMove};
+use crate::move_gen::{self, Move, MoveGen, StagedMoveGen, UndoInfo};
 use crate::piece::Piece;
-use crate::utils::{square_mask, Casteling, Color, Kind, Square};
+use crate::utils::{
+    square_mask, Casteling, CastleSide, Color, Kind, Square, CLEAR_FILE, EAST_RAY, MASK_RANK,
+    NORTH_EAST_RAY, NORTH_RAY, NORTH_WEST_RAY, SOUTH_EAST_RAY, SOUTH_RAY, SOUTH_WEST_RAY, WEST_RAY,
+};
+use crate::zobrist::ZOBRIST;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Board {
     // Who is it to move (White/Black)
     pub to_move: Color,
@@ -34,13 +42,85 @@ pub struct Board {
     // Is there a `En Passant` square
     pub en_passant: Option<Square>,
 
-    // Undo stack to allow efficient search
-    pub undo_stack: Vec<Undo>,
+    // Undo stack to allow efficient search, not part of the position
+    #[serde(skip)]
+    pub undo_stack: Vec<UndoInfo>,
+
+    // Zobrist hash of the position, kept up to date by `do_move`
+    pub hash: u64,
+
+    // Number of half moves since the last capture or pawn move
+    pub halfmove_clock: u32,
+
+    // Number of the current move, starting at 1 and incremented after Black
+    pub fullmove_number: u32,
+
+    // Files of the kingside and queenside castling rooks of White then
+    // Black, h and a in standard chess but not in Chess960
+    pub castling_rook_files: [[u8; 2]; 2],
+
+    // Whether this is a Chess960 game, whose FEN castling rights are
+    // written as rook files (Shredder-FEN)
+    pub chess960: bool,
+}
+
+/// Outcome of a game
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameResult {
+    Ongoing,
+    WhiteWins,
+    BlackWins,
+    Draw(DrawReason),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DrawReason {
+    Stalemate,
+    Threefold,
+    FiftyMove,
+    InsufficientMaterial,
+}
+
+/// State of the position for the side to move, from its legal moves
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameStatus {
+    Ongoing,
+    // The color that is checkmated
+    Checkmate(Color),
+    Stalemate,
+    FiftyMoveDraw,
+}
+
+/// The files next to the file of `square`
+fn adjacent_files(square: Square) -> Bitboard {
+    let file = !CLEAR_FILE[usize::from(square.file())];
+    ((file << 1) & CLEAR_FILE[0]) | ((file >> 1) & CLEAR_FILE[7])
+}
+
+/// The squares in front of `square` from the point of view of `color`, on
+/// its file and the adjacent ones
+fn front_span(square: Square, color: Color) -> Bitboard {
+    let ahead = match color {
+        Color::White => NORTH_RAY[square as usize],
+        Color::Black => SOUTH_RAY[square as usize],
+    };
+    ahead | ((ahead << 1) & CLEAR_FILE[0]) | ((ahead >> 1) & CLEAR_FILE[7])
 }
 
+/// Squares of the light color, used to compare bishops
+const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
+/// The FEN character of each castling right, in FEN order
+const CASTLING_CHARS: [(char, Color, CastleSide); 4] = [
+    ('K', Color::White, CastleSide::King),
+    ('Q', Color::White, CastleSide::Queen),
+    ('k', Color::Black, CastleSide::King),
+    ('q', Color::Black, CastleSide::Queen),
+];
+
 impl Default for Board {
     fn default() -> Self {
-        Board {
+        let mut board = Board {
             to_move: Color::White,
             white_pawn: Piece::create_initial(Kind::Pawn, Color::White),
             white_knight: Piece::create_initial(Kind::Knight, Color::White),
@@ -57,7 +137,51 @@ impl Default for Board {
             casteling_rights: Casteling::default(),
             en_passant: None,
             undo_stack: Vec::with_capacity(500),
-        }
+            hash: 0,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            castling_rook_files: [[7, 0]; 2],
+            chess960: false,
+        };
+        board.hash = board.zobrist_hash();
+        board
+    }
+}
+
+// Two boards are equal when they hold the same position, whatever the move
+// clocks and the moves played to reach it
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_move == other.to_move
+            && self
+                .pieces()
+                .iter()
+                .zip(other.pieces())
+                .all(|(a, b)| a.bitboard == b.bitboard)
+            && self.casteling_rights == other.casteling_rights
+            && CASTLING_CHARS.iter().all(|&(_, color, side)| {
+                !self.casteling_rights.get(color, side)
+                    || self.castling_rook_file(color, side)
+                        == other.castling_rook_file(color, side)
+            })
+            && self.en_passant == other.en_passant
+    }
+}
+
+impl Eq for Board {}
+
+// Equal boards have the same Zobrist hash
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl FromStr for Board {
+    type Err = ChessMgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Board::from_fen(s)
     }
 }
 
@@ -65,25 +189,59 @@ impl fmt::Display for Board {
     // Used to display a board in a formatter
     // Very useful to debug
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for rank in (0..8).rev() {
-            write!(f, "{} ", rank + 1)?;
-            for file in 0..8 {
-                let piece_ref: Option<&Piece> =
-                    self.get_piece(Square::from_u8(u8::try_from(rank * 8 + file).unwrap()));
-                let symbol = match piece_ref {
-                    Some(p) => p.get_char(),
+        f.write_str(&self.display_from(Color::White))
+    }
+}
+
+impl Board {
+    /// FEN of the standard starting position
+    pub const STARTPOS_FEN: &'static str =
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Render the board as seen by `perspective`: White sees rank 8 at the top
+    /// and files a-h, Black sees rank 1 at the top and files h-a
+    #[must_use]
+    pub fn display_from(&self, perspective: Color) -> String {
+        self.render(perspective, Piece::get_char)
+    }
+
+    /// Same as `display_from` but with FEN letters instead of Unicode glyphs
+    #[must_use]
+    pub fn display_ascii_from(&self, perspective: Color) -> String {
+        self.render(perspective, Piece::get_ascii_char)
+    }
+
+    fn render(&self, perspective: Color, glyph: fn(&Piece) -> char) -> String {
+        // Index 0 is the top-left corner from the viewer's side
+        let orient = |i: u8| match perspective {
+            Color::White => 7 - i,
+            Color::Black => i,
+        };
+        let mut out = String::new();
+        for row in 0..8 {
+            let rank = orient(row);
+            out.push(char::from(b'1' + rank));
+            out.push(' ');
+            for col in 0..8 {
+                let file = 7 - orient(col);
+                let symbol = match self.get_piece(Square::from_rank_file(rank, file)) {
+                    Some(p) => glyph(p),
                     None => '.',
                 };
-                write!(f, "{symbol} ")?;
+                out.push(symbol);
+                out.push(' ');
             }
-            writeln!(f)?;
+            out.push('\n');
         }
-        writeln!(f, "  a b c d e f g h")?;
-        write!(f, "")
+        out.push(' ');
+        for col in 0..8 {
+            out.push(' ');
+            out.push(char::from(b'a' + 7 - orient(col)));
+        }
+        out.push('\n');
+        out
     }
-}
 
-impl Board {
     pub fn get_piece(&self, square: Square) -> Option<&Piece> {
         let square_mask: Bitboard = square_mask(square);
         if (self.white_pawn.bitboard & square_mask) != 0 {
@@ -115,6 +273,32 @@ impl Board {
         }
     }
 
+    /// A board without any piece, White to move and without castling rights,
+    /// to be filled with `set_piece`.
+    pub fn empty() -> Self {
+        let mut board = Self::zero();
+        board.hash = board.zobrist_hash();
+        board
+    }
+
+    /// Puts a piece of `kind` and `color` on `square`, replacing the piece
+    /// standing there if any.
+    pub fn set_piece(&mut self, square: Square, kind: Kind, color: Color) {
+        self.remove_piece(square);
+        self.piece_mut(kind, color).bitboard |= square_mask(square);
+        self.hash = self.zobrist_hash();
+    }
+
+    /// Removes the piece standing on `square` and returns its kind and
+    /// color, or `None` if the square is empty.
+    /// The castling rights and en passant square are left as they are.
+    pub fn remove_piece(&mut self, square: Square) -> Option<(Kind, Color)> {
+        let (color, kind) = self.piece_at(square)?;
+        self.piece_mut(kind, color).bitboard &= !square_mask(square);
+        self.hash = self.zobrist_hash();
+        Some((kind, color))
+    }
+
     fn zero() -> Self {
         Board {
             to_move: Color::White,
@@ -190,6 +374,14 @@ impl Board {
             en_passant: None,
 
             undo_stack: Vec::with_capacity(500),
+
+            hash: 0,
+
+            halfmove_clock: 0,
+            fullmove_number: 1,
+
+            castling_rook_files: [[7, 0]; 2],
+            chess960: false,
         }
     }
 
@@ -215,6 +407,450 @@ impl Board {
         self.all_white_pieces() | self.all_black_pieces()
     }
 
+    fn piece_ref(&self, kind: Kind, color: Color) -> &Piece {
+        match (kind, color) {
+            (Kind::Pawn, Color::White) => &self.white_pawn,
+            (Kind::King, Color::White) => &self.white_king,
+            (Kind::Bishop, Color::White) => &self.white_bishop,
+            (Kind::Knight, Color::White) => &self.white_knight,
+            (Kind::Rook, Color::White) => &self.white_rook,
+            (Kind::Queen, Color::White) => &self.white_queen,
+            (Kind::Pawn, Color::Black) => &self.black_pawn,
+            (Kind::King, Color::Black) => &self.black_king,
+            (Kind::Bishop, Color::Black) => &self.black_bishop,
+            (Kind::Knight, Color::Black) => &self.black_knight,
+            (Kind::Rook, Color::Black) => &self.black_rook,
+            (Kind::Queen, Color::Black) => &self.black_queen,
+        }
+    }
+
+    fn piece_mut(&mut self, kind: Kind, color: Color) -> &mut Piece {
+        match (kind, color) {
+            (Kind::Pawn, Color::White) => &mut self.white_pawn,
+            (Kind::King, Color::White) => &mut self.white_king,
+            (Kind::Bishop, Color::White) => &mut self.white_bishop,
+            (Kind::Knight, Color::White) => &mut self.white_knight,
+            (Kind::Rook, Color::White) => &mut self.white_rook,
+            (Kind::Queen, Color::White) => &mut self.white_queen,
+            (Kind::Pawn, Color::Black) => &mut self.black_pawn,
+            (Kind::King, Color::Black) => &mut self.black_king,
+            (Kind::Bishop, Color::Black) => &mut self.black_bishop,
+            (Kind::Knight, Color::Black) => &mut self.black_knight,
+            (Kind::Rook, Color::Black) => &mut self.black_rook,
+            (Kind::Queen, Color::Black) => &mut self.black_queen,
+        }
+    }
+
+    /// Index of `side` in `castling_rook_files`
+    fn castle_side_index(side: CastleSide) -> usize {
+        match side {
+            CastleSide::King => 0,
+            CastleSide::Queen => 1,
+        }
+    }
+
+    /// Index of `color` in `castling_rook_files`
+    fn castle_color_index(color: Color) -> usize {
+        match color {
+            Color::White => 0,
+            Color::Black => 1,
+        }
+    }
+
+    /// File of the rook castling on `side`, see `castling_rook_files`
+    fn castling_rook_file(&self, color: Color, side: CastleSide) -> u8 {
+        self.castling_rook_files[Self::castle_color_index(color)][Self::castle_side_index(side)]
+    }
+
+    /// Home square of the rook castling on `side`, whether or not it is
+    /// still there.
+    fn castling_rook_home(&self, color: Color, side: CastleSide) -> Square {
+        let file = self.castling_rook_file(color, side);
+        let rank = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        Square::from_u8(rank * 8 + file)
+    }
+
+    /// Square of the rook a castling right refers to, `None` if the right
+    /// is lost or the rook is not on its home square.
+    /// This is the corner rook in standard chess, and the rook on the
+    /// recorded `castling_rook_files` in Chess960.
+    pub fn castling_rook_square(&self, color: Color, side: CastleSide) -> Option<Square> {
+        if !self.casteling_rights.get(color, side) {
+            return None;
+        }
+        let square = self.castling_rook_home(color, side);
+        let rook = self.piece_ref(Kind::Rook, color).bitboard;
+        (rook & square_mask(square) != 0).then_some(square)
+    }
+
+    /// Moves the rook of a castling move, from its home square to next to
+    /// the king, or back when `undo` is set. Returns the squares it went
+    /// from and to.
+    fn move_castling_rook(&mut self, m: &Move, undo: bool) -> (Square, Square) {
+        let (home, rook_to) = self.castling_rook_squares(m);
+        let (from, to) = if undo {
+            (rook_to, home)
+        } else {
+            (home, rook_to)
+        };
+        let rook = self.piece_mut(Kind::Rook, m.piece_color);
+        rook.bitboard &= !square_mask(from);
+        rook.bitboard |= square_mask(to);
+        (from, to)
+    }
+
+    /// The square the rook of the castling move `m` starts from, and the
+    /// one it lands on
+    fn castling_rook_squares(&self, m: &Move) -> (Square, Square) {
+        // The king always lands on the g or c file, the rook next to it
+        let (side, rook_to) = if m.to.file() == 6 {
+            (CastleSide::King, Square::from_usize(m.to as usize - 1))
+        } else {
+            (CastleSide::Queen, Square::from_usize(m.to as usize + 1))
+        };
+        (self.castling_rook_home(m.piece_color, side), rook_to)
+    }
+
+    fn pieces(&self) -> [&Piece; 12] {
+        [
+            &self.white_pawn,
+            &self.white_knight,
+            &self.white_bishop,
+            &self.white_rook,
+            &self.white_queen,
+            &self.white_king,
+            &self.black_pawn,
+            &self.black_knight,
+            &self.black_bishop,
+            &self.black_rook,
+            &self.black_queen,
+            &self.black_king,
+        ]
+    }
+
+    /// Iterates over the occupied squares, with the piece on each of them.
+    /// Cheaper than calling `get_piece` on the 64 squares to draw the board.
+    pub fn iter_pieces(&self) -> impl Iterator<Item = (Square, &Piece)> {
+        self.pieces().into_iter().flat_map(|piece| {
+            let mut bitboard = piece.bitboard;
+            std::iter::from_fn(move || {
+                bitboard
+                    .pop_lsb()
+                    .map(|square| (Square::from_usize(square), piece))
+            })
+        })
+    }
+
+    /// Computes the Zobrist hash of the position from scratch.
+    /// It accounts for the pieces, the side to move, the castling rights
+    /// and the en passant file.
+    pub fn zobrist_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for piece in self.pieces() {
+            for square in piece.bitboard.iter_squares() {
+                hash ^= ZOBRIST.piece(piece.kind, piece.color, square);
+            }
+        }
+
+        hash ^= self.rights_hash();
+
+        if self.to_move == Color::Black {
+            hash ^= ZOBRIST.black_to_move;
+        }
+
+        hash
+    }
+
+    /// Part of the hash for the castling rights and the en passant square,
+    /// which a move may change in many ways and is easier to XOR out and
+    /// back in as a whole.
+    fn rights_hash(&self) -> u64 {
+        let mut hash = 0;
+        for ((_, color, side), key) in CASTLING_CHARS.iter().zip(ZOBRIST.castling) {
+            if self.casteling_rights.get(*color, *side) {
+                hash ^= key;
+            }
+        }
+        if let Some(square) = self.en_passant {
+            hash ^= ZOBRIST.en_passant[usize::from(square.file())];
+        }
+        hash
+    }
+
+    /// Whether the stored `hash` matches a freshly computed `zobrist_hash`.
+    /// Meant to catch bugs in the incremental hash updates.
+    pub fn verify_hash(&self) -> bool {
+        self.hash == self.zobrist_hash()
+    }
+
+    /// Changes the side to move, e.g. when setting up a position by hand.
+    /// The en passant square is cleared if the new side could not capture
+    /// on it, see `is_en_passant_capturable`.
+    pub fn set_to_move(&mut self, color: Color) {
+        self.to_move = color;
+        if self
+            .en_passant
+            .is_some_and(|ep| !self.is_en_passant_capturable(ep, color))
+        {
+            self.en_passant = None;
+        }
+        self.hash = self.zobrist_hash();
+    }
+
+    /// Whether a pawn of `color` attacks the en passant square `square`,
+    /// which must be on the 6th rank for White, the 3rd for Black.
+    /// The en passant square is only kept when this holds, so that the same
+    /// position always has the same hash.
+    fn is_en_passant_capturable(&self, square: Square, color: Color) -> bool {
+        let ep_rank = match color {
+            Color::White => 5,
+            Color::Black => 2,
+        };
+        square.rank() == ep_rank
+            && self.attackers_to(square, color) & self.piece_ref(Kind::Pawn, color).bitboard != 0
+    }
+
+    /// A copy of the board with `color` to move, see `set_to_move`.
+    #[must_use]
+    pub fn with_side_to_move(&self, color: Color) -> Board {
+        let mut board = self.clone();
+        board.set_to_move(color);
+        board
+    }
+
+    /// The captures the opponent could play if it were their turn,
+    /// i.e. what they threaten after a null move. The moves are only
+    /// pseudo legal.
+    pub fn opponent_threats(&self) -> Vec<Move> {
+        let board = self.with_side_to_move(self.to_move.opposite());
+        let mut mg = MoveGen::new(&board);
+        mg.gen_captures();
+        mg.pseudo_move_list
+    }
+
+    /// Key identifying the position for repetition and transposition
+    /// detection. The move clocks are not part of it, so the same position
+    /// reached by different move orders has the same key.
+    pub fn repetition_key(&self) -> u64 {
+        self.hash
+    }
+
+    /// Number of pieces of the given kind and color on the board
+    fn count(&self, kind: Kind, color: Color) -> i32 {
+        // A bitboard holds at most 64 pieces, so this always fits
+        i32::try_from(self.piece_ref(kind, color).bitboard.count_ones()).unwrap_or(64)
+    }
+
+    /// Total material of one side, in centipawns (see `Kind::value`).
+    pub fn material(&self, color: Color) -> i32 {
+        [
+            Kind::Pawn,
+            Kind::Knight,
+            Kind::Bishop,
+            Kind::Rook,
+            Kind::Queen,
+        ]
+        .iter()
+        .map(|&kind| self.count(kind, color) * kind.value())
+        .sum()
+    }
+
+    /// Material difference in centipawns, positive when White is ahead.
+    pub fn material_balance(&self) -> i32 {
+        self.material(Color::White) - self.material(Color::Black)
+    }
+
+    /// Number of pseudo legal moves of `color`, whether or not it is its
+    /// turn to move.
+    pub fn mobility(&self, color: Color) -> u32 {
+        let mut mg = MoveGen::new(self);
+        mg.gen_moves_for(color);
+        u32::try_from(mg.get_pseudo_moves().len()).unwrap_or(u32::MAX)
+    }
+
+    /// Pawns of `color` with no enemy pawn in front of them on their file
+    /// or the adjacent ones.
+    pub fn passed_pawns(&self, color: Color) -> Bitboard {
+        let enemy_pawns = self.piece_ref(Kind::Pawn, color.opposite()).bitboard;
+        self.piece_ref(Kind::Pawn, color)
+            .bitboard
+            .iter_squares()
+            .filter(|&square| front_span(square, color) & enemy_pawns == 0)
+            .collect()
+    }
+
+    /// Pawns of `color` with no pawn of the same color on the adjacent files.
+    pub fn isolated_pawns(&self, color: Color) -> Bitboard {
+        let pawns = self.piece_ref(Kind::Pawn, color).bitboard;
+        pawns
+            .iter_squares()
+            .filter(|&square| adjacent_files(square) & pawns == 0)
+            .collect()
+    }
+
+    /// Pawns of `color` sharing their file with another pawn of the same color.
+    pub fn doubled_pawns(&self, color: Color) -> Bitboard {
+        let pawns = self.piece_ref(Kind::Pawn, color).bitboard;
+        pawns
+            .iter_squares()
+            .filter(|&square| {
+                let file = !CLEAR_FILE[usize::from(square.file())];
+                (pawns & file).count_ones() > 1
+            })
+            .collect()
+    }
+
+    /// Whether the side to move is checkmated, stalemated, drawn by the
+    /// fifty-move rule or can play on, generating the legal moves only once.
+    /// A checkmate on the last move before the fifty-move rule still counts.
+    pub fn status(&self) -> GameStatus {
+        if self.legal_move_count() > 0 {
+            if self.is_fifty_move_draw() {
+                GameStatus::FiftyMoveDraw
+            } else {
+                GameStatus::Ongoing
+            }
+        } else if self.is_in_check(self.to_move) {
+            GameStatus::Checkmate(self.to_move)
+        } else {
+            GameStatus::Stalemate
+        }
+    }
+
+    /// Whether 50 moves by each side (100 plies) were played without a
+    /// pawn move or a capture.
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// Whether the side to move is checkmated.
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check(self.to_move) && self.legal_move_count() == 0
+    }
+
+    /// Whether the side to move is stalemated.
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check(self.to_move) && self.legal_move_count() == 0
+    }
+
+    /// Whether neither side can ever checkmate: only kings remain, with at
+    /// most a single minor piece, or bishops all on squares of the same color.
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy = self.white_pawn.bitboard
+            | self.black_pawn.bitboard
+            | self.white_rook.bitboard
+            | self.black_rook.bitboard
+            | self.white_queen.bitboard
+            | self.black_queen.bitboard;
+        if heavy != 0 {
+            return false;
+        }
+        let knights = self.white_knight.bitboard | self.black_knight.bitboard;
+        let bishops = (self.white_bishop.bitboard | self.black_bishop.bitboard).0;
+        if (knights.0 | bishops).count_ones() <= 1 {
+            return true;
+        }
+        knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
+    /// Whether the position is a known draw with correct play, even if a
+    /// checkmate is still possible: insufficient material, or two knights
+    /// against a bare king, which cannot force mate.
+    /// Unlike `is_insufficient_material`, this does not end the game under
+    /// the rules.
+    pub fn is_theoretical_draw(&self) -> bool {
+        if self.is_insufficient_material() {
+            return true;
+        }
+        let white = self.all_white_pieces() & !self.white_king.bitboard;
+        let black = self.all_black_pieces() & !self.black_king.bitboard;
+        let two_knights =
+            |side: Bitboard, knights: Bitboard| side == knights && knights.count_ones() == 2;
+        (black == 0 && two_knights(white, self.white_knight.bitboard))
+            || (white == 0 && two_knights(black, self.black_knight.bitboard))
+    }
+
+    /// Whether the game is over by checkmate, stalemate, insufficient
+    /// material or the fifty-move rule. Repetitions need the history of
+    /// the game, so they are not detected here.
+    pub fn is_game_over(&self) -> bool {
+        self.game_over_reason().is_some()
+    }
+
+    /// Why the game is over, `None` if it is not, see `is_game_over`.
+    pub fn game_over_reason(&self) -> Option<GameResult> {
+        if self.legal_move_count() == 0 {
+            if !self.is_in_check(self.to_move) {
+                return Some(GameResult::Draw(DrawReason::Stalemate));
+            }
+            return Some(match self.to_move {
+                Color::White => GameResult::BlackWins,
+                Color::Black => GameResult::WhiteWins,
+            });
+        }
+        if self.is_insufficient_material() {
+            return Some(GameResult::Draw(DrawReason::InsufficientMaterial));
+        }
+        if self.is_fifty_move_draw() {
+            return Some(GameResult::Draw(DrawReason::FiftyMove));
+        }
+        None
+    }
+
+    /// Whether the position should be considered an endgame.
+    ///
+    /// Uses the usual heuristic: it is an endgame if each side either has
+    /// no queen, or has a queen and at most one minor piece besides it.
+    pub fn is_endgame(&self) -> bool {
+        [Color::White, Color::Black].iter().all(|&color| {
+            let queens = self.count(Kind::Queen, color);
+            let pieces = self.material(color)
+                - queens * Kind::Queen.value()
+                - self.count(Kind::Pawn, color) * Kind::Pawn.value();
+            queens == 0 || pieces <= Kind::Bishop.value()
+        })
+    }
+
+    /// The color and kind of the piece standing on `square`, if any.
+    pub fn piece_at(&self, square: Square) -> Option<(Color, Kind)> {
+        self.get_piece(square)
+            .map(|piece| (piece.color, piece.kind))
+    }
+
+    /// Mailbox view of the board, indexed as `[rank][file]` with rank 1 and
+    /// file a at index 0.
+    #[must_use]
+    pub fn to_array(&self) -> [[Option<(Color, Kind)>; 8]; 8] {
+        let mut array = [[None; 8]; 8];
+        for (rank, row) in (0u8..).zip(array.iter_mut()) {
+            for (file, cell) in (0u8..).zip(row.iter_mut()) {
+                *cell = self.piece_at(Square::from_rank_file(rank, file));
+            }
+        }
+        array
+    }
+
+    /// Builds a board from a mailbox laid out as in `to_array`.
+    /// Like `empty`, White is to move and nobody can castle.
+    #[must_use]
+    pub fn from_array(array: &[[Option<(Color, Kind)>; 8]; 8]) -> Self {
+        let mut board = Self::zero();
+        for (rank, row) in (0u8..).zip(array.iter()) {
+            for (file, cell) in (0u8..).zip(row.iter()) {
+                if let Some((color, kind)) = *cell {
+                    board.piece_mut(kind, color).bitboard |=
+                        square_mask(Square::from_rank_file(rank, file));
+                }
+            }
+        }
+        board.hash = board.zobrist_hash();
+        board
+    }
+
     pub fn get_piece_kind(&self, square: Square) -> Option<Kind> {
         let square_mask: Bitboard = square_mask(square);
         if (self.white_pawn.bitboard & square_mask) != 0 {
@@ -253,113 +889,610 @@ impl Board {
         }
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "It is not suppose to panic")]
-    pub fn is_in_check(&self, color: Color) -> bool {
-        match color {
-            Color::White => {
-                let king_square =
-                    Square::from_usize(self.white_king.bitboard.clone().pop_lsb().unwrap());
-                let mg = MoveGen {
-                    board: self,
-                    pseudo_move_list: Vec::new(),
-                    legal_move_list: Vec::new(),
-                };
-                mg.is_square_under_attack(king_square, Color::Black)
-            }
-            Color::Black => {
-                let king_square =
-                    Square::from_usize(self.black_king.bitboard.clone().pop_lsb().unwrap());
-                let mg = MoveGen {
-                    board: self,
-                    pseudo_move_list: Vec::new(),
-                    legal_move_list: Vec::new(),
-                };
-                mg.is_square_under_attack(king_square, Color::White)
-            }
-        }
+    /// Every legal move of the side to move, in Standard Algebraic Notation.
+    pub fn legal_moves_san(&self) -> Vec<String> {
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        let moves = mg.get_legal_moves();
+        moves.iter().map(|m| m.to_san_with(self, moves)).collect()
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "It is not suppose to panic")]
-    #[allow(clippy::too_many_lines)]
-    pub fn do_move(&mut self, m: &Move) {
-        // Push on the stack to keep track of the rights for undo_move
-        let undo = Undo {
-            captured_piece: m
-                .captured_piece
-                .map(|kind| (kind, m.piece_color.opposite(), m.to)),
-            castling_rights: self.casteling_rights.clone(),
-            en_passant: self.en_passant,
-            to_move: self.to_move,
+    /// Parses a move in UCI long algebraic notation (`e2e4`, `e7e8q`,
+    /// `e1g1` for castling), the flags of the move being inferred from
+    /// the position.
+    ///
+    /// # Errors
+    /// Returns `InvalidMove` if the string is malformed or is not a legal
+    /// move for the side to move.
+    pub fn parse_uci(&self, s: &str) -> Result<Move, ChessMgError> {
+        let invalid = || InvalidMove(s.to_string());
+        if !(4..=5).contains(&s.len()) {
+            return Err(invalid());
+        }
+        let from = s.get(0..2).ok_or_else(invalid)?;
+        let to = s.get(2..4).ok_or_else(invalid)?;
+        let from = Square::from_str(from).map_err(|_| invalid())?;
+        let to = Square::from_str(to).map_err(|_| invalid())?;
+        let promotion = match s.get(4..) {
+            Some("") => None,
+            Some("q") => Some(Kind::Queen),
+            Some("r") => Some(Kind::Rook),
+            Some("b") => Some(Kind::Bishop),
+            Some("n") => Some(Kind::Knight),
+            _ => return Err(invalid()),
         };
 
-        self.undo_stack.push(undo);
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        mg.legal_move_list
+            .into_iter()
+            .find(|m| m.from == from && m.to == to && m.promoting_piece == promotion)
+            .ok_or_else(invalid)
+    }
 
-        // Determine the piece to modify
-        let piece = match (m.piece_kind, m.piece_color) {
-            (Kind::Pawn, Color::White) => &mut self.white_pawn,
-            (Kind::King, Color::White) => &mut self.white_king,
-            (Kind::Bishop, Color::White) => &mut self.white_bishop,
-            (Kind::Knight, Color::White) => &mut self.white_knight,
-            (Kind::Rook, Color::White) => &mut self.white_rook,
-            (Kind::Queen, Color::White) => &mut self.white_queen,
-            (Kind::Pawn, Color::Black) => &mut self.black_pawn,
-            (Kind::King, Color::Black) => &mut self.black_king,
-            (Kind::Bishop, Color::Black) => &mut self.black_bishop,
-            (Kind::Knight, Color::Black) => &mut self.black_knight,
-            (Kind::Rook, Color::Black) => &mut self.black_rook,
-            (Kind::Queen, Color::Black) => &mut self.black_queen,
+    /// Parses a move in Standard Algebraic Notation (`Nbd2`, `exd5`,
+    /// `e8=Q`, `O-O-O`), matching it against the legal moves of the side
+    /// to move. Trailing `+`, `#`, `!` and `?` annotations are ignored.
+    ///
+    /// # Errors
+    /// Returns `InvalidMove` if the string is malformed, or if it matches
+    /// no legal move or more than one.
+    pub fn parse_san(&self, san: &str) -> Result<Move, ChessMgError> {
+        let invalid = |reason: &str| InvalidMove(format!("{san}: {reason}"));
+        let token = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        let moves = mg.legal_move_list;
+
+        let castle_file = match token {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
         };
-        // Generate the masks
-        let from_bitboard = square_mask(m.from);
-        let to_bitboard = square_mask(m.to);
+        if let Some(file) = castle_file {
+            return moves
+                .into_iter()
+                .find(|m| m.casteling && m.to.file() == file)
+                .ok_or_else(|| invalid("castling is not legal"));
+        }
 
-        // Execute move
-        piece.bitboard = piece.bitboard & !from_bitboard;
-
-        // If the rook move, or the king, remove the casteling rights
-        if piece.kind == Kind::Rook && piece.color == Color::White {
-            match m.from {
-                Square::H1 => self.casteling_rights.white_kingside = false,
-                Square::A1 => self.casteling_rights.white_queenside = false,
-                _ => (),
+        // Piece letter, upper case so that `b` stays a file
+        let (kind, rest) = match token.chars().next() {
+            Some('N') => (Kind::Knight, &token[1..]),
+            Some('B') => (Kind::Bishop, &token[1..]),
+            Some('R') => (Kind::Rook, &token[1..]),
+            Some('Q') => (Kind::Queen, &token[1..]),
+            Some('K') => (Kind::King, &token[1..]),
+            _ => (Kind::Pawn, token),
+        };
+
+        // Promotion suffix, with or without the `=`
+        let (rest, promotion) = match rest.char_indices().last() {
+            Some((i, c)) if c.is_ascii_uppercase() => {
+                let promotion = match c {
+                    'N' => Kind::Knight,
+                    'B' => Kind::Bishop,
+                    'R' => Kind::Rook,
+                    'Q' => Kind::Queen,
+                    _ => return Err(invalid("unknown promotion piece")),
+                };
+                (rest[..i].trim_end_matches('='), Some(promotion))
             }
+            _ => (rest, None),
+        };
+
+        if rest.len() < 2 || !rest.is_ascii() {
+            return Err(invalid("missing destination square"));
         }
-        if piece.kind == Kind::Rook && piece.color == Color::Black {
-            match m.from {
-                Square::H8 => self.casteling_rights.black_kingside = false,
-                Square::A8 => self.casteling_rights.black_queenside = false,
-                _ => (),
+        let (hint, to) = rest.split_at(rest.len() - 2);
+        let to = Square::from_str(to).map_err(|_| invalid("invalid destination square"))?;
+        let capture = hint.ends_with('x');
+        let hint = hint.trim_end_matches('x');
+
+        // Disambiguation: a file, a rank, or both
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in hint.chars() {
+            match c {
+                'a'..='h' if from_file.is_none() => from_file = Some(c as u8 - b'a'),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+                _ => return Err(invalid("invalid disambiguation")),
             }
         }
-        if piece.kind == Kind::King {
-            match piece.color {
-                Color::White => {
-                    self.casteling_rights.white_kingside = false;
-                    self.casteling_rights.white_queenside = false;
-                }
-                Color::Black => {
-                    self.casteling_rights.black_kingside = false;
-                    self.casteling_rights.black_queenside = false;
+
+        // A pawn capture is written with its file and the `x`, a pawn push
+        // without, while the `x` of a piece capture may be left out
+        if kind == Kind::Pawn && capture && from_file.is_none() {
+            return Err(invalid("pawn capture without its file"));
+        }
+
+        let mut candidates = moves.into_iter().filter(|m| {
+            let (file, rank) = m.from.to_coords();
+            let takes = m.captured_piece.is_some() || m.en_passant;
+            !m.casteling
+                && m.piece_kind == kind
+                && m.to == to
+                && m.promoting_piece == promotion
+                && if kind == Kind::Pawn {
+                    capture == takes
+                } else {
+                    !capture || takes
                 }
-            }
+                && from_file.is_none_or(|f| f == file)
+                && from_rank.is_none_or(|r| r == rank)
+        });
+        let m = candidates
+            .next()
+            .ok_or_else(|| invalid("no legal move matches"))?;
+        if let Some(other) = candidates.next() {
+            return Err(invalid(&format!(
+                "ambiguous, matches both {} and {}",
+                m.to_uci(),
+                other.to_uci()
+            )));
         }
+        Ok(m)
+    }
 
-        // If the move is a promotion, it is not useful to make the pawn appear
-        // So we only care when there is no promotion
-        if m.promoting_piece.is_none() {
-            piece.bitboard = piece.bitboard | to_bitboard;
+    /// The legal moves of the side to move, by value, e.g.
+    /// `board.legal_moves().filter(|m| m.captured_piece.is_some())`.
+    pub fn legal_moves(&self) -> impl Iterator<Item = Move> {
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        mg.legal_move_list.into_iter()
+    }
+
+    /// The legal moves of the side to move, staged for search: captures
+    /// first, by most valuable victim and least valuable attacker, then
+    /// quiet moves. See `StagedMoveGen`.
+    pub fn staged_moves(&self) -> StagedMoveGen<'_> {
+        StagedMoveGen::new(self)
+    }
+
+    /// The legal moves of the piece on `square`, e.g. to highlight its
+    /// destinations once selected in a UI. Empty if the square is empty or
+    /// holds a piece of the side not to move.
+    pub fn legal_moves_from(&self, square: Square) -> Vec<Move> {
+        self.legal_moves().filter(|m| m.from == square).collect()
+    }
+
+    /// Whether playing `m` puts the enemy king in check, directly, by a
+    /// discovered attack, with the rook of a castling move or with a
+    /// promoted piece. Works on bitboards, without playing the move.
+    pub fn gives_check(&self, m: &Move) -> bool {
+        let color = m.piece_color;
+        let king = self.king_square(color.opposite());
+        let king_mask = square_mask(king);
+
+        // The squares left by the pieces that moved or were captured
+        let mut vacated = square_mask(m.from) | square_mask(m.captured_square());
+        let mut landed = square_mask(m.to);
+        let rook_to = m.casteling.then(|| {
+            let (home, rook_to) = self.castling_rook_squares(m);
+            vacated |= square_mask(home);
+            landed |= square_mask(rook_to);
+            rook_to
+        });
+        let occupancy = (self.all_pieces() & !vacated) | landed;
+
+        let kind = m.promoting_piece.unwrap_or(m.piece_kind);
+        if move_gen::piece_attacks(kind, color, m.to, occupancy) & king_mask != 0 {
+            return true;
         }
+        if rook_to.is_some_and(|rook_to| {
+            move_gen::piece_attacks(Kind::Rook, color, rook_to, occupancy) & king_mask != 0
+        }) {
+            return true;
+        }
+        // Discovered checks, from the pieces that stayed in place
+        move_gen::attackers_to(self, king, color, occupancy) & !vacated != 0
+    }
 
-        // Handle the edge cases (promotion, casteling, double_push,
-        // captures)
+    /// The legal move packed as `compact` by `Move::to_compact`, or `None`
+    /// if it is not legal in this position, e.g. a stale table entry.
+    pub fn move_from_compact(&self, compact: u16) -> Option<Move> {
+        self.legal_moves().find(|m| m.to_compact() == compact)
+    }
 
-        // Captures
-        if m.captured_piece.is_some() {
-            let enemy_kind = m.captured_piece.unwrap();
-            let enemy_color = match m.piece_color {
-                Color::White => Color::Black,
-                Color::Black => Color::White,
+    /// Whether `m`, e.g. built by a UI or received from the network, is
+    /// one of the legal moves of the side to move, flags included.
+    pub fn is_legal(&self, m: &Move) -> bool {
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        mg.get_legal_moves().contains(m)
+    }
+
+    /// A legal move of the side to move chosen uniformly at random, or
+    /// `None` if the game is over. Meant for random movers and fuzzing.
+    pub fn random_legal_move<R: Rng>(&self, rng: &mut R) -> Option<Move> {
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        let mut moves = mg.legal_move_list;
+        if moves.is_empty() {
+            return None;
+        }
+        let index = rng.random_range(0..moves.len());
+        Some(moves.swap_remove(index))
+    }
+
+    /// Number of legal moves for the side to move.
+    /// 0 means checkmate if the side is in check, stalemate otherwise.
+    /// Same as `count_legal_moves`.
+    pub fn legal_move_count(&self) -> usize {
+        self.count_legal_moves()
+    }
+
+    /// Number of legal moves for the side to move, without collecting them.
+    pub fn count_legal_moves(&self) -> usize {
+        MoveGen::without_capacity(self).count_legal_moves()
+    }
+
+    /// Number of leaf nodes of the legal move tree of depth `depth`, used
+    /// to check the move generator against known counts.
+    pub fn perft(&self, depth: u32) -> u64 {
+        self.clone().perft_nodes(depth)
+    }
+
+    /// Node count of `perft(depth - 1)` after each legal move, to find the
+    /// moves where a wrong count comes from. Empty at depth 0.
+    pub fn perft_divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut board = self.clone();
+        self.legal_moves()
+            .map(|m| {
+                let undo = board.make_move(&m);
+                let nodes = board.perft_nodes(depth - 1);
+                board.unmake_move(&m, undo);
+                (m, nodes)
+            })
+            .collect()
+    }
+
+    /// Same as `perft`, with the root moves split across threads by
+    /// rayon. Each root move is searched on its own copy of the board.
+    #[cfg(feature = "parallel")]
+    pub fn perft_parallel(&self, depth: u32) -> u64 {
+        use rayon::prelude::*;
+
+        if depth == 0 {
+            return 1;
+        }
+        let moves: Vec<Move> = self.legal_moves().collect();
+        moves
+            .par_iter()
+            .map(|m| {
+                let mut board = self.clone();
+                board.make_move(m);
+                board.perft_nodes(depth - 1)
+            })
+            .sum()
+    }
+
+    fn perft_nodes(&mut self, depth: u32) -> u64 {
+        match depth {
+            0 => return 1,
+            // The leaves only need to be counted
+            1 => return self.count_legal_moves() as u64,
+            _ => {}
+        }
+        let mut nodes = 0;
+        for m in self.legal_moves().collect::<Vec<_>>() {
+            let undo = self.make_move(&m);
+            nodes += self.perft_nodes(depth - 1);
+            self.unmake_move(&m, undo);
+        }
+        nodes
+    }
+
+    /// Whether `color` has a legal move landing on `target`, as if it were
+    /// its turn to move.
+    pub fn can_any_piece_reach(&self, target: Square, color: Color) -> bool {
+        let board = self.with_side_to_move(color);
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        mg.get_legal_moves().iter().any(|m| m.to == target)
+    }
+
+    /// Best material gain over the legal captures of the side to move,
+    /// or 0 if no capture wins material.
+    /// Each capture is scored by its static exchange evaluation, see `see`.
+    pub fn max_capture_gain(&self) -> i32 {
+        let mut mg = MoveGen::new(self);
+        mg.gen_legal_moves();
+        mg.get_legal_moves()
+            .iter()
+            .filter(|m| m.captured_piece.is_some())
+            .map(|m| self.see(m))
+            .fold(0, i32::max)
+    }
+
+    /// Static Exchange Evaluation: material won (positive) or lost
+    /// (negative) by the side playing `m` once both sides have recaptured
+    /// on `m.to` with their least valuable attacker, each side being free
+    /// to stop the sequence when recapturing would lose material.
+    /// Pieces uncovered behind a capturer (x-rays) join the exchange.
+    pub fn see(&self, m: &Move) -> i32 {
+        // The king may only capture last, which a high value enforces
+        let value = |kind: Kind| match kind {
+            Kind::King => 10 * Kind::Queen.value(),
+            kind => kind.value(),
+        };
+
+        let mut occupancy =
+            self.all_pieces() & !square_mask(m.from) & !square_mask(m.captured_square());
+        // gains[d] is the balance for the side capturing at depth d, if the
+        // sequence stopped right after its capture
+        let mut gains = vec![m.captured_piece.map_or(0, value)];
+        let mut on_square = match m.promoting_piece {
+            Some(promotion) => {
+                gains[0] += promotion.value() - Kind::Pawn.value();
+                value(promotion)
+            }
+            None => value(m.piece_kind),
+        };
+        let mut side = m.piece_color.opposite();
+
+        loop {
+            let attackers = move_gen::attackers_to(self, m.to, side, occupancy) & occupancy;
+            let Some((square, kind)) = [
+                Kind::Pawn,
+                Kind::Knight,
+                Kind::Bishop,
+                Kind::Rook,
+                Kind::Queen,
+                Kind::King,
+            ]
+            .into_iter()
+            .find_map(|kind| {
+                let square = (self.piece_ref(kind, side).bitboard & attackers)
+                    .iter_squares()
+                    .next()?;
+                Some((square, kind))
+            }) else {
+                break;
+            };
+            gains.push(on_square - gains[gains.len() - 1]);
+            on_square = value(kind);
+            occupancy &= !square_mask(square);
+            side = side.opposite();
+        }
+
+        // Each side only recaptures if it does not lose material by doing so
+        while gains.len() > 1 {
+            let last = gains.pop().unwrap_or_default();
+            let previous = gains.len() - 1;
+            gains[previous] = -i32::max(-gains[previous], last);
+        }
+        gains[0]
+    }
+
+    /// Each ray comes with the sliders of `enemy` able to pin along it, and
+    /// whether square indices increase when walking away from the king
+    fn pin_rays(&self, enemy: Color) -> [(&'static [Bitboard; 64], Bitboard, bool); 8] {
+        let enemy_queens = self.piece_ref(Kind::Queen, enemy).bitboard;
+        let straight_sliders = self.piece_ref(Kind::Rook, enemy).bitboard | enemy_queens;
+        let diagonal_sliders = self.piece_ref(Kind::Bishop, enemy).bitboard | enemy_queens;
+        [
+            (&NORTH_RAY, straight_sliders, true),
+            (&EAST_RAY, straight_sliders, true),
+            (&SOUTH_RAY, straight_sliders, false),
+            (&WEST_RAY, straight_sliders, false),
+            (&NORTH_EAST_RAY, diagonal_sliders, true),
+            (&NORTH_WEST_RAY, diagonal_sliders, true),
+            (&SOUTH_EAST_RAY, diagonal_sliders, false),
+            (&SOUTH_WEST_RAY, diagonal_sliders, false),
+        ]
+    }
+
+    /// The pieces of `color` pinned to their king: the only piece between
+    /// the king and an enemy rook, bishop or queen moving along that line.
+    pub fn pinned(&self, color: Color) -> Bitboard {
+        let Some(king) = self.piece_ref(Kind::King, color).bitboard.clone().pop_lsb() else {
+            return Bitboard(0);
+        };
+        let own = match color {
+            Color::White => self.all_white_pieces(),
+            Color::Black => self.all_black_pieces(),
+        };
+
+        let mut pinned = Bitboard(0);
+        for (ray, sliders, increasing) in self.pin_rays(color.opposite()) {
+            let blockers = ray[king] & self.all_pieces();
+            let Some(first) = nearest_square(blockers, increasing) else {
+                continue;
+            };
+            let first = Bitboard(1 << first);
+            let Some(second) = nearest_square(blockers & !first, increasing) else {
+                continue;
             };
+            if own & first != 0 && sliders & Bitboard(1 << second) != 0 {
+                pinned |= first;
+            }
+        }
+        pinned
+    }
+
+    /// If the piece on `square` is pinned to its king, returns the squares
+    /// it may still move to: the ray between its king and the pinner,
+    /// the pinner included.
+    /// Returns `None` if the square is empty, holds a king, or if the piece
+    /// is not pinned.
+    pub fn pin_ray_for(&self, square: Square) -> Option<Bitboard> {
+        let piece = self.get_piece(square)?;
+        if piece.kind == Kind::King {
+            return None;
+        }
+        let king = self
+            .piece_ref(Kind::King, piece.color)
+            .bitboard
+            .clone()
+            .pop_lsb()?;
+
+        let (ray, sliders, increasing) = self
+            .pin_rays(piece.color.opposite())
+            .into_iter()
+            .find(|(ray, _, _)| ray[king] & square_mask(square) != 0)?;
+
+        // The piece must be the first one met from the king, and the next one
+        // must be an enemy slider moving along that ray
+        let blockers = ray[king] & self.all_pieces();
+        if nearest_square(blockers, increasing)? != square as usize {
+            return None;
+        }
+        let pinner = nearest_square(blockers & !square_mask(square), increasing)?;
+        if sliders & Bitboard(1 << pinner) == 0 {
+            return None;
+        }
+
+        Some(ray[king] & !ray[pinner] & !square_mask(square))
+    }
+
+    /// The pieces of `by` attacking `square`, whether or not they could
+    /// legally capture on it.
+    pub fn attackers_to(&self, square: Square, by: Color) -> Bitboard {
+        move_gen::attackers_to(self, square, by, self.all_pieces())
+    }
+
+    /// Every square attacked by a piece of `by`, whether or not the piece
+    /// could legally move there. Pieces of `by` defended by another one are
+    /// included.
+    pub fn attacked_squares(&self, by: Color) -> Bitboard {
+        move_gen::attacked_squares(self, by, self.all_pieces())
+    }
+
+    /// Whether a piece of `by` attacks `square`.
+    pub fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        self.attackers_to(square, by) != 0
+    }
+
+    /// The square of the king of `color`.
+    ///
+    /// # Panics
+    /// Panics if `color` has no king.
+    pub fn king_square(&self, color: Color) -> Square {
+        let king = self.piece_ref(Kind::King, color).bitboard;
+        debug_assert_eq!(king.count_ones(), 1, "Expected exactly one {color:?} king");
+        Square::from_usize(king.clone().pop_lsb().expect("No king on the board"))
+    }
+
+    /// The enemy pieces giving check to the side to move. More than one
+    /// means a double check, which only a king move can answer. None on a
+    /// board where the side to move has no king.
+    pub fn checkers(&self) -> Bitboard {
+        let Some(king) = self
+            .piece_ref(Kind::King, self.to_move)
+            .bitboard
+            .clone()
+            .pop_lsb()
+        else {
+            return Bitboard(0);
+        };
+        self.attackers_to(Square::from_usize(king), self.to_move.opposite())
+    }
+
+    /// Whether the king of `color` is attacked, false if it has no king.
+    pub fn is_in_check(&self, color: Color) -> bool {
+        let Some(king) = self.piece_ref(Kind::King, color).bitboard.clone().pop_lsb() else {
+            return false;
+        };
+        self.is_square_attacked(Square::from_usize(king), color.opposite())
+    }
+
+    /// Plays the move, keeping what is needed to take it back on
+    /// `undo_stack`, see `undo_move`.
+    pub fn do_move(&mut self, m: &Move) {
+        let undo = self.make_move(m);
+        self.undo_stack.push(undo);
+    }
+
+    /// Takes back the last move played with `do_move`.
+    ///
+    /// # Panics
+    /// Panics if no move was played with `do_move`.
+    pub fn undo_move(&mut self, m: &Move) {
+        let undo = self.undo_stack.pop().expect("Undo stack underflow");
+        self.unmake_move(m, undo);
+    }
+
+    /// Plays the move and returns what is needed to take it back
+    /// with `unmake_move`. Cheaper than cloning the board to try a move.
+    #[allow(clippy::missing_panics_doc, reason = "It is not suppose to panic")]
+    #[allow(clippy::too_many_lines)]
+    pub fn make_move(&mut self, m: &Move) -> UndoInfo {
+        let undo = UndoInfo {
+            captured: m.captured_piece.map(|kind| (kind, m.captured_square())),
+            castling_rights: self.casteling_rights.clone(),
+            en_passant: self.en_passant,
+            to_move: self.to_move,
+            hash: self.hash,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        };
+
+        // The hash is updated incrementally, the rights are put back in once
+        // the move is done
+        let mut hash = self.hash ^ self.rights_hash() ^ ZOBRIST.black_to_move;
+        hash ^= ZOBRIST.piece(m.piece_kind, m.piece_color, m.from);
+        hash ^= ZOBRIST.piece(
+            m.promoting_piece.unwrap_or(m.piece_kind),
+            m.piece_color,
+            m.to,
+        );
+        if let Some(captured) = m.captured_piece {
+            hash ^= ZOBRIST.piece(captured, m.piece_color.opposite(), m.captured_square());
+        }
+
+        // Moving the king or a castling rook loses the castling right, and
+        // so does having that rook captured
+        for side in [CastleSide::King, CastleSide::Queen] {
+            let own_rook = self.castling_rook_home(m.piece_color, side);
+            if m.piece_kind == Kind::King || (m.piece_kind == Kind::Rook && m.from == own_rook) {
+                self.casteling_rights.set(m.piece_color, side, false);
+            }
+            let enemy = m.piece_color.opposite();
+            if m.captured_piece == Some(Kind::Rook) && m.to == self.castling_rook_home(enemy, side)
+            {
+                self.casteling_rights.set(enemy, side, false);
+            }
+        }
+
+        // Determine the piece to modify
+        let piece = match (m.piece_kind, m.piece_color) {
+            (Kind::Pawn, Color::White) => &mut self.white_pawn,
+            (Kind::King, Color::White) => &mut self.white_king,
+            (Kind::Bishop, Color::White) => &mut self.white_bishop,
+            (Kind::Knight, Color::White) => &mut self.white_knight,
+            (Kind::Rook, Color::White) => &mut self.white_rook,
+            (Kind::Queen, Color::White) => &mut self.white_queen,
+            (Kind::Pawn, Color::Black) => &mut self.black_pawn,
+            (Kind::King, Color::Black) => &mut self.black_king,
+            (Kind::Bishop, Color::Black) => &mut self.black_bishop,
+            (Kind::Knight, Color::Black) => &mut self.black_knight,
+            (Kind::Rook, Color::Black) => &mut self.black_rook,
+            (Kind::Queen, Color::Black) => &mut self.black_queen,
+        };
+        // Generate the masks
+        let from_bitboard = square_mask(m.from);
+        let to_bitboard = square_mask(m.to);
+
+        // Execute move
+        piece.bitboard &= !from_bitboard;
+
+        // If the move is a promotion, it is not useful to make the pawn appear
+        // So we only care when there is no promotion
+        if m.promoting_piece.is_none() {
+            piece.bitboard |= to_bitboard;
+        }
+
+        // Handle the edge cases (promotion, casteling, double_push,
+        // captures)
+
+        // Captures
+        if let Some(enemy_kind) = m.captured_piece {
+            let enemy_color = m.piece_color.opposite();
             let enemy_piece = match (enemy_kind, enemy_color) {
                 (Kind::Pawn, Color::White) => &mut self.white_pawn,
                 (Kind::King, Color::White) => &mut self.white_king,
@@ -380,39 +1513,19 @@ impl Board {
             if m.en_passant {
                 match enemy_color {
                     Color::White => {
-                        enemy_piece.bitboard =
-                            enemy_piece.bitboard & !(square_mask(self.en_passant.unwrap()) << 8);
+                        enemy_piece.bitboard &= !(square_mask(self.en_passant.unwrap()) << 8);
                     }
                     Color::Black => {
-                        enemy_piece.bitboard =
-                            enemy_piece.bitboard & !(square_mask(self.en_passant.unwrap()) >> 8);
+                        enemy_piece.bitboard &= !(square_mask(self.en_passant.unwrap()) >> 8);
                     }
                 }
             } else {
-                enemy_piece.bitboard = enemy_piece.bitboard & !to_bitboard;
-            }
-
-            if enemy_piece.kind == Kind::Rook && enemy_piece.color == Color::White {
-                if m.to == Square::H1 {
-                    self.casteling_rights.white_kingside = false;
-                }
-                if m.to == Square::A1 {
-                    self.casteling_rights.white_queenside = false;
-                }
-            }
-            if enemy_piece.kind == Kind::Rook && enemy_piece.color == Color::Black {
-                if m.to == Square::H8 {
-                    self.casteling_rights.black_kingside = false;
-                }
-                if m.to == Square::A8 {
-                    self.casteling_rights.black_queenside = false;
-                }
+                enemy_piece.bitboard &= !to_bitboard;
             }
         }
 
         // Promotion
-        if m.promoting_piece.is_some() {
-            let piece_kind = m.promoting_piece.unwrap();
+        if let Some(piece_kind) = m.promoting_piece {
             let new_piece = match (piece_kind, m.piece_color) {
                 (Kind::Pawn, Color::White) => &mut self.white_pawn,
                 (Kind::King, Color::White) => &mut self.white_king,
@@ -429,53 +1542,89 @@ impl Board {
                 (Kind::Queen, Color::Black) => &mut self.black_queen,
             };
             // Make the new piece appear
-            new_piece.bitboard = new_piece.bitboard | to_bitboard;
+            new_piece.bitboard |= to_bitboard;
         }
 
         // Double_push
         if m.double_push {
             let s_to = m.to as usize;
             let s_from = m.from as usize;
-            self.en_passant = Some(Square::from_usize((s_to + s_from) / 2));
+            let square = Square::from_usize(usize::midpoint(s_to, s_from));
+            self.en_passant = self
+                .is_en_passant_capturable(square, m.piece_color.opposite())
+                .then_some(square);
         } else {
             self.en_passant = None;
         }
 
         // Casteling
         if m.casteling {
-            match m.to {
-                Square::G1 => {
-                    self.white_rook.bitboard = self.white_rook.bitboard & !square_mask(Square::H1);
-                    self.white_rook.bitboard = self.white_rook.bitboard | square_mask(Square::F1);
-                }
-                Square::C1 => {
-                    self.white_rook.bitboard = self.white_rook.bitboard & !square_mask(Square::A1);
-                    self.white_rook.bitboard = self.white_rook.bitboard | square_mask(Square::D1);
-                }
-                Square::G8 => {
-                    self.black_rook.bitboard = self.black_rook.bitboard & !square_mask(Square::H8);
-                    self.black_rook.bitboard = self.black_rook.bitboard | square_mask(Square::F8);
-                }
-                Square::C8 => {
-                    self.black_rook.bitboard = self.black_rook.bitboard & !square_mask(Square::A8);
-                    self.black_rook.bitboard = self.black_rook.bitboard | square_mask(Square::D8);
-                }
-                _ => panic!(),
-            }
+            let (from, to) = self.move_castling_rook(m, false);
+            hash ^= ZOBRIST.piece(Kind::Rook, m.piece_color, from);
+            hash ^= ZOBRIST.piece(Kind::Rook, m.piece_color, to);
         }
 
-        self.to_move = match self.to_move {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
+        // Move counters
+        if m.piece_kind == Kind::Pawn || m.captured_piece.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.to_move == Color::Black {
+            self.fullmove_number += 1;
         }
+
+        self.to_move = self.to_move.opposite();
+
+        self.hash = hash ^ self.rights_hash();
+        debug_assert!(self.verify_hash(), "Incremental hash is out of sync");
+
+        undo
     }
 
-    pub fn undo_move(&mut self, m: &Move) {
-        let undo = self.undo_stack.pop().expect("Undo stack underflow");
+    /// Passes the turn without moving a piece, for null move pruning.
+    /// The en passant square is cleared, while the castling rights and
+    /// the move counters are left as they are.
+    /// Returns what is needed to take it back with `unmake_null_move`.
+    pub fn make_null_move(&mut self) -> UndoInfo {
+        let undo = UndoInfo {
+            captured: None,
+            castling_rights: self.casteling_rights.clone(),
+            en_passant: self.en_passant,
+            to_move: self.to_move,
+            hash: self.hash,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        };
+
+        if let Some(square) = self.en_passant.take() {
+            self.hash ^= ZOBRIST.en_passant[usize::from(square.file())];
+        }
+        self.to_move = self.to_move.opposite();
+        self.hash ^= ZOBRIST.black_to_move;
+        debug_assert!(self.verify_hash(), "Incremental hash is out of sync");
+
+        undo
+    }
+
+    /// Takes back a null move played with `make_null_move`.
+    pub fn unmake_null_move(&mut self, undo: UndoInfo) {
+        self.casteling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.to_move = undo.to_move;
+        self.hash = undo.hash;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+    }
 
+    /// Takes back a move played with `make_move`, given what it returned.
+    pub fn unmake_move(&mut self, m: &Move, undo: UndoInfo) {
         self.casteling_rights = undo.castling_rights;
         self.en_passant = undo.en_passant;
         self.to_move = undo.to_move;
+        self.hash = undo.hash;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
 
         // Remove moved piece from destination, put it back on origin
         let piece = match (m.piece_kind, m.piece_color) {
@@ -492,8 +1641,8 @@ impl Board {
             (Kind::Rook, Color::Black) => &mut self.black_rook,
             (Kind::Queen, Color::Black) => &mut self.black_queen,
         };
-        piece.bitboard = piece.bitboard & !square_mask(m.to);
-        piece.bitboard = piece.bitboard | square_mask(m.from);
+        piece.bitboard &= !square_mask(m.to);
+        piece.bitboard |= square_mask(m.from);
 
         // Handle promotion
         if let Some(prom) = m.promoting_piece {
@@ -513,12 +1662,12 @@ impl Board {
                 (Kind::Queen, Color::Black) => &mut self.black_queen,
             };
 
-            promoted_piece.bitboard = promoted_piece.bitboard & !square_mask(m.to);
+            promoted_piece.bitboard &= !square_mask(m.to);
         }
 
         // Restore captured piece if there was one
-        if let Some((kind, color, square)) = undo.captured_piece {
-            let enemy_piece = match (kind, color) {
+        if let Some((kind, square)) = undo.captured {
+            let enemy_piece = match (kind, m.piece_color.opposite()) {
                 (Kind::Pawn, Color::White) => &mut self.white_pawn,
                 (Kind::King, Color::White) => &mut self.white_king,
                 (Kind::Bishop, Color::White) => &mut self.white_bishop,
@@ -532,32 +1681,12 @@ impl Board {
                 (Kind::Rook, Color::Black) => &mut self.black_rook,
                 (Kind::Queen, Color::Black) => &mut self.black_queen,
             };
-            enemy_piece.bitboard = enemy_piece.bitboard | square_mask(square);
+            enemy_piece.bitboard |= square_mask(square);
         }
 
         // Handle castling (rook movement back)
         if m.casteling {
-            match m.to {
-                Square::G1 => {
-                    // white king side
-                    self.white_rook.bitboard = self.white_rook.bitboard & !square_mask(Square::F1);
-                    self.white_rook.bitboard = self.white_rook.bitboard | square_mask(Square::H1);
-                }
-                Square::C1 => {
-                    // white queen side
-                    self.white_rook.bitboard = self.white_rook.bitboard & square_mask(Square::D1);
-                    self.white_rook.bitboard = self.white_rook.bitboard | square_mask(Square::A1);
-                }
-                Square::G8 => {
-                    self.black_rook.bitboard = self.black_rook.bitboard & !square_mask(Square::F8);
-                    self.black_rook.bitboard = self.black_rook.bitboard | square_mask(Square::H8);
-                }
-                Square::C8 => {
-                    self.black_rook.bitboard = self.black_rook.bitboard & !square_mask(Square::D8);
-                    self.black_rook.bitboard = self.black_rook.bitboard | square_mask(Square::A8);
-                }
-                _ => {}
-            }
+            self.move_castling_rook(m, true);
         }
     }
 
@@ -570,51 +1699,58 @@ impl Board {
 
         let parts: Vec<&str> = fen.split_whitespace().collect();
         if parts.len() < 4 {
-            return Err(InvalidFEN("Expected at least 4 fields".to_string()));
+            return Err(InvalidFEN(format!(
+                "Too few fields: expected at least 4, found {}",
+                parts.len()
+            )));
         }
 
         // piece placement (ranks from 8 down to 1)
         let ranks: Vec<&str> = parts[0].split('/').collect();
         if ranks.len() != 8 {
-            return Err(InvalidFEN("Expected 8 ranks".to_string()));
+            return Err(InvalidFEN(format!(
+                "Expected 8 ranks, found {}",
+                ranks.len()
+            )));
         }
 
         for (rank_idx, rank_str) in ranks.iter().enumerate() {
+            // Rank number and column as written in the FEN, for error messages
+            let rank = 8 - rank_idx;
             let mut file: usize = 0;
-            for ch in rank_str.chars() {
-                if ch.is_ascii_digit() {
-                    file += ch.to_digit(10).unwrap() as usize;
+            for (col, ch) in rank_str.chars().enumerate() {
+                let col = col + 1;
+                if let Some(digit) = ch.to_digit(10) {
+                    if !(1..=8).contains(&digit) {
+                        return Err(InvalidFEN(format!(
+                            "Invalid digit '{ch}' in rank {rank} at column {col}"
+                        )));
+                    }
+                    file += digit as usize;
+                    if file > 8 {
+                        return Err(InvalidFEN(format!(
+                            "Rank {rank} has more than 8 files (digit '{ch}' at column {col})"
+                        )));
+                    }
                 } else {
                     if file >= 8 {
-                        return Err(InvalidFEN("Too many squares in rank".to_string()));
+                        return Err(InvalidFEN(format!(
+                            "Rank {rank} has more than 8 files (piece '{ch}' at column {col})"
+                        )));
                     }
                     // compute square index for a1 = 0 .. h8 = 63
-                    let sq = u32::try_from((7 - rank_idx) * 8 + file).unwrap();
-                    let bit = 1u64 << sq;
-
-                    match ch {
-                        'P' => board.white_pawn.bitboard.0 |= bit,
-                        'N' => board.white_knight.bitboard.0 |= bit,
-                        'B' => board.white_bishop.bitboard.0 |= bit,
-                        'R' => board.white_rook.bitboard.0 |= bit,
-                        'Q' => board.white_queen.bitboard.0 |= bit,
-                        'K' => board.white_king.bitboard.0 |= bit,
-
-                        'p' => board.black_pawn.bitboard.0 |= bit,
-                        'n' => board.black_knight.bitboard.0 |= bit,
-                        'b' => board.black_bishop.bitboard.0 |= bit,
-                        'r' => board.black_rook.bitboard.0 |= bit,
-                        'q' => board.black_queen.bitboard.0 |= bit,
-                        'k' => board.black_king.bitboard.0 |= bit,
-
-                        _ => return Err(InvalidFEN(format!("Invalid piece char {ch}"))),
-                    }
+                    let bit = 1u64 << ((7 - rank_idx) * 8 + file);
+
+                    let Some((kind, color)) = Kind::from_char(ch) else {
+                        return Err(InvalidFEN(format!("Invalid piece char {ch}")));
+                    };
+                    board.piece_mut(kind, color).bitboard.0 |= bit;
 
                     file += 1;
                 }
             }
             if file != 8 {
-                return Err(InvalidFEN("A rank did not fill 8 files".to_string()));
+                return Err(InvalidFEN(format!("Rank {rank} has only {file} files")));
             }
         }
 
@@ -622,28 +1758,137 @@ impl Board {
         board.to_move = match parts.get(1) {
             Some(&"w") => Color::White,
             Some(&"b") => Color::Black,
-            _ => return Err(InvalidFEN("Active color is invalid".to_string())),
+            _ => {
+                return Err(InvalidFEN(format!(
+                    "Invalid active color {}, expected w or b",
+                    parts[1]
+                )));
+            }
         };
 
-        // castling rights
-        let rights = parts[2];
-        board.casteling_rights.white_kingside = rights.contains('K');
-        board.casteling_rights.white_queenside = rights.contains('Q');
-        board.casteling_rights.black_kingside = rights.contains('k');
-        board.casteling_rights.black_queenside = rights.contains('q');
+        board.parse_castling_rights(parts[2])?;
 
         // en passant target
         let ep = parts[3];
         if ep == "-" {
             board.en_passant = None;
         } else {
-            // TODO: return custom error
-            board.en_passant = Some(Square::from_str(ep)?);
+            let square = Square::from_str(ep)
+                .map_err(|_| InvalidFEN(format!("Invalid en passant square {ep}")))?;
+            // Kept only if the side to move can take on it
+            board.en_passant = board
+                .is_en_passant_capturable(square, board.to_move)
+                .then_some(square);
+        }
+
+        // move counters, optional
+        if let Some(halfmove) = parts.get(4) {
+            board.halfmove_clock = halfmove
+                .parse()
+                .map_err(|_| InvalidFEN(format!("Invalid halfmove clock {halfmove}")))?;
         }
+        if let Some(fullmove) = parts.get(5) {
+            board.fullmove_number = fullmove
+                .parse()
+                .map_err(|_| InvalidFEN(format!("Invalid fullmove number {fullmove}")))?;
+        }
+
+        board.hash = board.zobrist_hash();
 
         Ok(board)
     }
 
+    /// Parses an EPD record: the first four fields of a FEN followed by
+    /// operations such as `bm Nf3; id "WAC.001";`.
+    /// Returns the board and the operand of each opcode, without the
+    /// quotes of string operands.
+    ///
+    /// # Errors
+    /// Returns `InvalidFEN` if the board fields are not valid, see
+    /// `from_fen`.
+    pub fn from_epd(epd: &str) -> Result<(Board, HashMap<String, String>), ChessMgError> {
+        let mut fields = epd.split_whitespace();
+        let position: Vec<&str> = fields.by_ref().take(4).collect();
+        let board = Board::from_fen(&position.join(" "))?;
+
+        // Operations end with `;`, which may also appear in quoted strings
+        let rest: Vec<&str> = fields.collect();
+        let rest = rest.join(" ");
+        let mut operations = HashMap::new();
+        let mut in_quotes = false;
+        for operation in rest.split(|c| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ';' && !in_quotes
+        }) {
+            let operation = operation.trim();
+            if operation.is_empty() {
+                continue;
+            }
+            let (opcode, operand) = operation.split_once(' ').unwrap_or((operation, ""));
+            let operand = operand.trim();
+            let operand = operand
+                .strip_prefix('"')
+                .and_then(|o| o.strip_suffix('"'))
+                .unwrap_or(operand);
+            operations.insert(opcode.to_string(), operand.to_string());
+        }
+        Ok((board, operations))
+    }
+
+    /// Parses the castling rights field of a FEN, as `KQkq` or as the files
+    /// of the castling rooks (Shredder-FEN, for Chess960), setting the rights
+    /// and the castling rook files.
+    fn parse_castling_rights(&mut self, field: &str) -> Result<(), ChessMgError> {
+        for ch in field.chars().filter(|&ch| ch != '-') {
+            let color = if ch.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let home_rank = match color {
+                Color::White => MASK_RANK[0],
+                Color::Black => MASK_RANK[7],
+            };
+            let king = self.piece_ref(Kind::King, color).bitboard & home_rank;
+            let king_file = king.iter_squares().next().map_or(4, Square::file);
+            let rook_files: Vec<u8> = (self.piece_ref(Kind::Rook, color).bitboard & home_rank)
+                .iter_squares()
+                .map(Square::file)
+                .collect();
+
+            // With KQkq, the outermost rook on the side of the king
+            let (side, file) = match ch.to_ascii_lowercase() {
+                'k' => {
+                    let file = rook_files.iter().copied().filter(|&f| f > king_file).max();
+                    (CastleSide::King, file.unwrap_or(7))
+                }
+                'q' => {
+                    let file = rook_files.iter().copied().filter(|&f| f < king_file).min();
+                    (CastleSide::Queen, file.unwrap_or(0))
+                }
+                file @ 'a'..='h' => {
+                    self.chess960 = true;
+                    let file = file as u8 - b'a';
+                    if file > king_file {
+                        (CastleSide::King, file)
+                    } else {
+                        (CastleSide::Queen, file)
+                    }
+                }
+                _ => return Err(InvalidFEN(format!("Invalid castling right {ch}"))),
+            };
+            self.casteling_rights.set(color, side, true);
+            self.castling_rook_files[Self::castle_color_index(color)]
+                [Self::castle_side_index(side)] = file;
+            if king_file != 4 || file != [7, 0][Self::castle_side_index(side)] {
+                self.chess960 = true;
+            }
+        }
+        Ok(())
+    }
+
     pub fn to_fen(&self) -> String {
         let mut fen = String::new();
 
@@ -675,26 +1920,24 @@ impl Board {
 
         // 2. Active color
         fen.push(' ');
-        fen.push(match self.to_move {
-            Color::White => 'w',
-            Color::Black => 'b',
-        });
+        fen.push(self.to_move.to_char());
 
         // 3. Castling rights
         fen.push(' ');
-        let mut castling = String::new();
-        if self.casteling_rights.white_kingside {
-            castling.push('K');
-        }
-        if self.casteling_rights.white_queenside {
-            castling.push('Q');
-        }
-        if self.casteling_rights.black_kingside {
-            castling.push('k');
-        }
-        if self.casteling_rights.black_queenside {
-            castling.push('q');
-        }
+        let mut castling: String = CASTLING_CHARS
+            .iter()
+            .filter(|&&(_, color, side)| self.casteling_rights.get(color, side))
+            .map(|&(ch, color, side)| {
+                if !self.chess960 {
+                    return ch;
+                }
+                let file = (b'a' + self.castling_rook_file(color, side)) as char;
+                match color {
+                    Color::White => file.to_ascii_uppercase(),
+                    Color::Black => file,
+                }
+            })
+            .collect();
         if castling.is_empty() {
             castling.push('-');
         }
@@ -703,59 +1946,52 @@ impl Board {
         // 4. En passant target square
         fen.push(' ');
         if let Some(square) = self.en_passant {
-            fen.push_str(square.square_to_str()); // you need a Square -> algebraic conversion
+            fen.push_str(square.square_to_str());
         } else {
             fen.push('-');
         }
 
-        // 5. Halfmove clock (optional, set 0)
-        fen.push_str(" 0");
+        // 5. Halfmove clock
+        fen.push(' ');
+        fen.push_str(&self.halfmove_clock.to_string());
 
-        // 6. Fullmove number (optional, set 1)
-        fen.push_str(" 1");
+        // 6. Fullmove number
+        fen.push(' ');
+        fen.push_str(&self.fullmove_number.to_string());
 
         fen
     }
 
     fn piece_at_square(board: &Board, square: usize) -> Option<char> {
-        let pieces = [
-            board.white_pawn.clone(),
-            board.white_knight.clone(),
-            board.white_bishop.clone(),
-            board.white_rook.clone(),
-            board.white_queen.clone(),
-            board.white_king.clone(),
-            board.black_pawn.clone(),
-            board.black_knight.clone(),
-            board.black_bishop.clone(),
-            board.black_rook.clone(),
-            board.black_queen.clone(),
-            board.black_king.clone(),
-        ];
+        board
+            .get_piece(Square::from_usize(square))
+            .map(Piece::get_fen_char)
+    }
+}
 
-        for piece in &pieces {
-            if piece.bitboard & Bitboard(1u64 << square) != 0 {
-                let c = match piece.kind {
-                    Kind::Pawn => 'p',
-                    Kind::Knight => 'n',
-                    Kind::Bishop => 'b',
-                    Kind::Rook => 'r',
-                    Kind::Queen => 'q',
-                    Kind::King => 'k',
-                };
-                return Some(match piece.color {
-                    Color::White => c.to_ascii_uppercase(),
-                    Color::Black => c,
-                });
-            }
-        }
+/// The set square of `bitboard` closest to the start of a ray,
+/// the ray going toward increasing indices or not.
+fn nearest_square(bitboard: Bitboard, increasing: bool) -> Option<usize> {
+    if bitboard == 0 {
         None
+    } else if increasing {
+        Some(bitboard.0.trailing_zeros() as usize)
+    } else {
+        Some(63 - bitboard.0.leading_zeros() as usize)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustc_hash::FxHashSet;
+
+    // Plays the first legal move going from `from` to `to`
+    fn play(board: &mut Board, uci: &str) -> Move {
+        let m = board.parse_uci(uci).expect("Move is not legal");
+        board.do_move(&m);
+        m
+    }
 
     #[test]
     fn test_to_fen() {
@@ -766,5 +2002,1389 @@ mod tests {
             s,
             "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"
         );
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            // En passant
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            // Partial castling rights
+            "r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_is_endgame() {
+        assert!(!Board::default().is_endgame());
+
+        let b = Board::from_fen("4k2r/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert!(b.is_endgame());
+
+        // A queen with a single minor piece is still an endgame
+        let b = Board::from_fen("3qk3/8/8/8/8/8/8/2BQK3 w - - 0 1").unwrap();
+        assert!(b.is_endgame());
+
+        // But not a queen backed by a rook
+        let b = Board::from_fen("3qk3/8/8/8/8/8/8/R2QK3 w - - 0 1").unwrap();
+        assert!(!b.is_endgame());
+    }
+
+    #[test]
+    fn test_pin_ray_for() {
+        let b = Board::from_fen("4r2k/8/8/8/8/8/4R3/4K3 w - - 0 1").unwrap();
+        let e_file = Bitboard::from_squares(&[
+            Square::E3,
+            Square::E4,
+            Square::E5,
+            Square::E6,
+            Square::E7,
+            Square::E8,
+        ]);
+        assert!(b.pin_ray_for(Square::E2) == Some(e_file));
+
+        // The pinned rook may only move along the file
+        let mut mg = MoveGen::new(&b);
+        mg.gen_legal_moves();
+        assert!(mg
+            .get_legal_moves()
+            .iter()
+            .filter(|m| m.from == Square::E2)
+            .all(|m| e_file & square_mask(m.to) != 0));
+
+        // Neither the king, the pinner, nor a piece behind another blocker are pinned
+        assert!(b.pin_ray_for(Square::E1).is_none());
+        assert!(b.pin_ray_for(Square::E8).is_none());
+        let b = Board::from_fen("4r2k/8/8/8/4N3/8/4R3/4K3 w - - 0 1").unwrap();
+        assert!(b.pin_ray_for(Square::E2).is_none());
+        assert!(b.pin_ray_for(Square::E4).is_none());
+
+        // Diagonal pin, toward decreasing indices
+        let b = Board::from_fen("7k/8/8/8/8/2B5/8/K3b3 b - - 0 1").unwrap();
+        assert!(b.pin_ray_for(Square::E1).is_none());
+        let b = Board::from_fen("7k/6n1/8/8/8/8/8/B6K b - - 0 1").unwrap();
+        assert!(b.pin_ray_for(Square::G7).is_some());
+    }
+
+    #[test]
+    fn test_verify_hash() {
+        assert!(Board::default().verify_hash());
+
+        // Capture
+        let mut b = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        play(&mut b, "e4d5");
+        assert!(b.verify_hash());
+
+        // Promotion
+        let mut b = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        play(&mut b, "b7b8q");
+        assert!(b.verify_hash());
+
+        // Castle
+        let mut b = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        play(&mut b, "e1g1");
+        assert!(b.verify_hash());
+
+        // En passant
+        let mut b = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        play(&mut b, "e5d6");
+        assert!(b.verify_hash());
+
+        // The hash is updated incrementally, so a desync is caught
+        b.hash ^= ZOBRIST.black_to_move;
+        assert!(!b.verify_hash());
+    }
+
+    #[test]
+    fn test_legal_moves_san() {
+        let san = Board::default().legal_moves_san();
+        assert_eq!(san.len(), 20);
+        for m in ["e4", "e3", "Nf3", "Nc3", "a4", "h3"] {
+            assert!(san.contains(&m.to_string()), "{m} is missing");
+        }
+
+        // Disambiguation, by file then by rank
+        let san = Board::from_fen("2k5/8/8/8/8/8/4K3/R6R w - - 0 1")
+            .unwrap()
+            .legal_moves_san();
+        assert!(san.contains(&"Rad1".to_string()));
+        assert!(san.contains(&"Rhd1".to_string()));
+        let san = Board::from_fen("2k5/R7/8/8/8/8/8/R3K3 w - - 0 1")
+            .unwrap()
+            .legal_moves_san();
+        assert!(san.contains(&"R1a4".to_string()));
+        assert!(san.contains(&"R7a4".to_string()));
+
+        // Captures, promotions, castling, check and mate
+        let san = Board::from_fen("3r4/4P3/6k1/8/8/8/8/R3K2R w KQ - 0 1")
+            .unwrap()
+            .legal_moves_san();
+        assert!(san.contains(&"exd8=Q".to_string()));
+        assert!(san.contains(&"e8=N".to_string()));
+        assert!(san.contains(&"O-O".to_string()));
+        // The rook on d8 covers d1
+        assert!(!san.contains(&"O-O-O".to_string()));
+        assert!(san.contains(&"e8=Q+".to_string()));
+        let san = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")
+            .unwrap()
+            .legal_moves_san();
+        assert!(san.contains(&"Ra8#".to_string()));
+    }
+
+    #[test]
+    fn test_undo_en_passant() {
+        for fen in [
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1",
+        ] {
+            let mut b = Board::from_fen(fen).unwrap();
+            let (uci, to, victim) = match b.to_move {
+                Color::White => ("e5d6", Square::D6, Square::D5),
+                Color::Black => ("d4e3", Square::E3, Square::E4),
+            };
+            let m = play(&mut b, uci);
+            assert!(b.get_piece_kind(victim).is_none());
+
+            b.undo_move(&m);
+            assert!(b.get_piece_kind(victim) == Some(Kind::Pawn));
+            assert!(b.get_piece_kind(to).is_none());
+            assert_eq!(b.to_fen(), fen);
+        }
+    }
+    #[test]
+    fn test_max_capture_gain() {
+        // The rook on d5 is not defended
+        let b = Board::from_fen("4k3/8/8/3r4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        assert_eq!(b.max_capture_gain(), 500);
+
+        // Taking the defended pawn loses the queen
+        let b = Board::from_fen("4k3/2p5/3p4/8/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        assert_eq!(b.max_capture_gain(), 0);
+
+        assert_eq!(Board::default().max_capture_gain(), 0);
+    }
+
+    #[test]
+    fn test_see() {
+        let see = |fen: &str, uci: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            board.see(&board.parse_uci(uci).unwrap())
+        };
+
+        // Winning: a pawn takes a knight defended by a pawn
+        assert_eq!(see("4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 220);
+        // Losing: the queen takes a pawn defended by a pawn
+        assert_eq!(see("4k3/2p5/3p4/8/8/8/3Q4/4K3 w - - 0 1", "d2d6"), -800);
+        // Even: rook for rook, the king taking back
+        assert_eq!(see("3rk3/8/8/8/8/8/8/3RK3 w - - 0 1", "d1d8"), 0);
+        // The rook behind joins the exchange, so Black does not recapture
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        // The king can't recapture a defended piece
+        assert_eq!(see("8/8/8/4k3/3p4/8/3R4/3RK3 w - - 0 1", "d2d4"), 100);
+    }
+
+    #[test]
+    fn test_set_to_move() {
+        // After 1. e4, the en passant square only makes sense for Black
+        let mut b = Board::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        b.set_to_move(Color::Black);
+        assert!(b.en_passant == Some(Square::E3));
+
+        b.set_to_move(Color::White);
+        assert!(b.en_passant.is_none());
+        assert!(b.verify_hash());
+        assert_eq!(b.to_fen(), "4k3/8/8/8/3pP3/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_legal_move_count() {
+        assert_eq!(Board::default().legal_move_count(), 20);
+
+        // Fool's mate
+        let b = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3")
+            .unwrap();
+        assert_eq!(b.legal_move_count(), 0);
+        assert!(b.is_in_check(Color::White));
+    }
+
+    #[test]
+    fn test_from_fen_rank_errors() {
+        let error = |fen: &str| match Board::from_fen(fen) {
+            Err(InvalidFEN(msg)) => msg,
+            _ => panic!("{fen} should be an invalid FEN"),
+        };
+
+        assert_eq!(
+            error("4k3/pppppppppp/8/8/8/8/8/4K3 w - - 0 1"),
+            "Rank 7 has more than 8 files (piece 'p' at column 9)"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8p/4K3 w - - 0 1"),
+            "Rank 2 has more than 8 files (piece 'p' at column 2)"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/44p/8/8/4K3 w - - 0 1"),
+            "Rank 4 has more than 8 files (piece 'p' at column 3)"
+        );
+        assert_eq!(
+            error("4k3/8/8/p8/8/8/8/4K3 w - - 0 1"),
+            "Rank 5 has more than 8 files (digit '8' at column 2)"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/7/8/4K3 w - - 0 1"),
+            "Rank 3 has only 7 files"
+        );
+        assert_eq!(
+            error("4k3/8/8/9/8/8/8/4K3 w - - 0 1"),
+            "Invalid digit '9' in rank 5 at column 1"
+        );
+        assert_eq!(
+            error("4k3/8/8/0p7/8/8/8/4K3 w - - 0 1"),
+            "Invalid digit '0' in rank 5 at column 1"
+        );
+    }
+
+    #[test]
+    fn test_from_fen_field_errors() {
+        let error = |fen: &str| match Board::from_fen(fen) {
+            Err(InvalidFEN(msg)) => msg,
+            _ => panic!("{fen} should be an invalid FEN"),
+        };
+
+        assert_eq!(
+            error("4k3/8/8/8/8/8/4K3 w - - 0 1"),
+            "Expected 8 ranks, found 7"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w -"),
+            "Too few fields: expected at least 4, found 3"
+        );
+        assert_eq!(error(""), "Too few fields: expected at least 4, found 0");
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            "Invalid active color x, expected w or b"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 white - - 0 1"),
+            "Invalid active color white, expected w or b"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - e9 0 1"),
+            "Invalid en passant square e9"
+        );
+    }
+
+    #[test]
+    fn test_iter_pieces() {
+        let b = Board::default();
+        assert_eq!(b.iter_pieces().count(), 32);
+
+        let (_, a1) = b
+            .iter_pieces()
+            .find(|(square, _)| *square == Square::A1)
+            .unwrap();
+        assert!(a1.kind == Kind::Rook && a1.color == Color::White);
+        assert_eq!(a1.get_char(), '♖');
+    }
+
+    #[test]
+    fn test_opponent_threats() {
+        // The bishop on b7 eyes the undefended rook on h1
+        let b = Board::from_fen("4k3/1b6/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let threats = b.opponent_threats();
+        assert!(threats.iter().any(|m| m.from == Square::B7
+            && m.to == Square::H1
+            && m.captured_piece == Some(Kind::Rook)));
+        assert!(threats.iter().all(|m| m.piece_color == Color::Black));
+
+        // The side to move of the board itself is unchanged
+        assert!(b.to_move == Color::White);
+        assert!(Board::default().opponent_threats().is_empty());
+    }
+
+    #[test]
+    fn test_can_any_piece_reach() {
+        let b = Board::default();
+        assert!(b.can_any_piece_reach(Square::E4, Color::White));
+        assert!(!b.can_any_piece_reach(Square::E6, Color::White));
+        assert!(b.can_any_piece_reach(Square::E6, Color::Black));
+        assert!(b.can_any_piece_reach(Square::F6, Color::Black));
+    }
+
+    #[test]
+    fn test_move_counters() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 7 42";
+        let b = Board::from_fen(fen).unwrap();
+        assert_eq!(b.halfmove_clock, 7);
+        assert_eq!(b.fullmove_number, 42);
+        assert_eq!(b.to_fen(), fen);
+
+        // The counters are optional
+        let b = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq -").unwrap();
+        assert_eq!(b.halfmove_clock, 0);
+        assert_eq!(b.fullmove_number, 1);
+
+        assert!(Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - x 1").is_err());
+    }
+
+    #[test]
+    fn test_startpos_fen() {
+        assert_eq!(Board::default().to_fen(), Board::STARTPOS_FEN);
+        assert!(Board::from_fen(Board::STARTPOS_FEN).unwrap().hash == Board::default().hash);
+        assert_eq!(Board::default().legal_move_count(), 20);
+    }
+
+    #[test]
+    fn test_move_counters_in_do_move() {
+        let mut b = Board::default();
+        let mut moves = Vec::new();
+        moves.push(play(&mut b, "g1f3"));
+        moves.push(play(&mut b, "g8f6"));
+        moves.push(play(&mut b, "b1c3"));
+        assert_eq!(b.halfmove_clock, 3);
+        assert_eq!(b.fullmove_number, 2);
+
+        // A pawn move resets the clock
+        moves.push(play(&mut b, "e7e5"));
+        assert_eq!(b.halfmove_clock, 0);
+        assert_eq!(b.fullmove_number, 3);
+        moves.push(play(&mut b, "c3d5"));
+        assert_eq!(b.halfmove_clock, 1);
+
+        // So does a capture
+        moves.push(play(&mut b, "f6d5"));
+        assert_eq!(b.halfmove_clock, 0);
+        assert_eq!(b.fullmove_number, 4);
+
+        for m in moves.iter().rev() {
+            b.undo_move(m);
+        }
+        assert_eq!(b.to_fen(), Board::STARTPOS_FEN);
+    }
+
+    #[test]
+    fn test_make_unmake_move() {
+        let snapshot = |b: &Board| {
+            let bitboards: Vec<u64> = b.pieces().iter().map(|p| p.bitboard.0).collect();
+            (bitboards, b.to_fen(), b.hash)
+        };
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+        ] {
+            let mut b = Board::from_fen(fen).unwrap();
+            let before = snapshot(&b);
+            let mut mg = MoveGen::new(&b);
+            mg.gen_legal_moves();
+            let moves = mg.get_legal_moves().clone();
+            for m in &moves {
+                let undo = b.make_move(m);
+                b.unmake_move(m, undo);
+                assert!(snapshot(&b) == before, "{m} broke {fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_uci() {
+        let b = Board::default();
+        let m = b.parse_uci("e2e4").unwrap();
+        assert!(m.piece_kind == Kind::Pawn && m.double_push);
+        let m = b.parse_uci("g1f3").unwrap();
+        assert!(m.piece_kind == Kind::Knight && m.captured_piece.is_none());
+
+        // Castling
+        let b = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(b.parse_uci("e1g1").unwrap().casteling);
+        assert!(b.parse_uci("e1c1").unwrap().casteling);
+
+        // Promotion, with a capture
+        let b = Board::from_fen("2r1k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let m = b.parse_uci("b7c8n").unwrap();
+        assert!(m.promoting_piece == Some(Kind::Knight));
+        assert!(m.captured_piece == Some(Kind::Rook));
+        assert!(b.parse_uci("b7b8").is_err());
+
+        // En passant
+        let b = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let m = b.parse_uci("e5d6").unwrap();
+        assert!(m.en_passant && m.captured_piece == Some(Kind::Pawn));
+
+        // Malformed or illegal
+        for s in ["", "e2", "e2e9", "e2e4x", "e1e3", "é2e4", "e2e4qq"] {
+            assert!(matches!(b.parse_uci(s), Err(InvalidMove(_))), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_game_over() {
+        assert!(!Board::default().is_game_over());
+        assert!(Board::default().game_over_reason().is_none());
+
+        // Fool's mate
+        let b = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3")
+            .unwrap();
+        assert!(b.is_checkmate());
+        assert_eq!(b.game_over_reason(), Some(GameResult::BlackWins));
+
+        let b = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(b.is_stalemate());
+        assert_eq!(
+            b.game_over_reason(),
+            Some(GameResult::Draw(DrawReason::Stalemate))
+        );
+
+        let b = Board::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert!(b.is_game_over());
+        assert_eq!(
+            b.game_over_reason(),
+            Some(GameResult::Draw(DrawReason::InsufficientMaterial))
+        );
+    }
+
+    #[test]
+    fn test_is_insufficient_material() {
+        for fen in [
+            "7k/8/8/8/8/8/8/K7 w - - 0 1",
+            "7k/8/8/8/8/8/8/KN6 w - - 0 1",
+            "7k/8/8/8/8/8/8/KB6 w - - 0 1",
+            // Bishops on squares of the same color
+            "6bk/8/8/8/8/8/8/KB6 w - - 0 1",
+        ] {
+            assert!(
+                Board::from_fen(fen).unwrap().is_insufficient_material(),
+                "{fen}"
+            );
+        }
+        for fen in [
+            "7k/8/8/8/8/8/8/KP6 w - - 0 1",
+            "7k/8/8/8/8/8/8/KNN5 w - - 0 1",
+            "7k/8/8/8/8/8/8/KBN5 w - - 0 1",
+            "5b1k/8/8/8/8/8/8/KB6 w - - 0 1",
+        ] {
+            assert!(
+                !Board::from_fen(fen).unwrap().is_insufficient_material(),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_castling_rook_square() {
+        let b = Board::default();
+        assert!(b.castling_rook_square(Color::White, CastleSide::King) == Some(Square::H1));
+        assert!(b.castling_rook_square(Color::Black, CastleSide::Queen) == Some(Square::A8));
+
+        let b = Board::from_fen("r3k3/8/8/8/8/8/8/R3K2R w Kq - 0 1").unwrap();
+        assert!(b
+            .castling_rook_square(Color::White, CastleSide::Queen)
+            .is_none());
+        assert!(b
+            .castling_rook_square(Color::Black, CastleSide::King)
+            .is_none());
+        assert!(b.castling_rook_square(Color::Black, CastleSide::Queen) == Some(Square::A8));
+
+        // Chess960, with the rooks on the b and f files given by a Shredder
+        // field, then by the outermost rooks of an X-FEN one
+        for fen in [
+            "1r2kr2/8/8/8/8/8/8/1R2KR2 w FBfb - 0 1",
+            "1r2kr2/8/8/8/8/8/8/1R2KR2 w KQkq - 0 1",
+        ] {
+            let b = Board::from_fen(fen).unwrap();
+            assert!(b.castling_rook_square(Color::White, CastleSide::King) == Some(Square::F1));
+            assert!(b.castling_rook_square(Color::White, CastleSide::Queen) == Some(Square::B1));
+            assert!(b.castling_rook_square(Color::Black, CastleSide::King) == Some(Square::F8));
+            assert!(b.castling_rook_square(Color::Black, CastleSide::Queen) == Some(Square::B8));
+        }
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let b = Board::default();
+        let m = b.parse_san("e4").unwrap();
+        assert!(m.from == Square::E2 && m.to == Square::E4);
+        let m = b.parse_san("Nf3!?").unwrap();
+        assert!(m.piece_kind == Kind::Knight && m.to == Square::F3);
+        assert!(b.parse_san("e5").is_err());
+        assert!(b.parse_san("Ke2").is_err());
+        assert!(b.parse_san("").is_err());
+
+        // Pawn capture, castling and check annotations
+        let b = Board::from_fen("r3k2r/8/8/3p4/4P3/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let m = b.parse_san("exd5").unwrap();
+        assert!(m.from == Square::E4 && m.to == Square::D5);
+        assert!(b.parse_san("O-O").unwrap().to == Square::G1);
+        assert!(b.parse_san("O-O-O+").unwrap().to == Square::C1);
+        assert!(b.parse_san("e5").unwrap().captured_piece.is_none());
+        assert!(b.parse_san("exd4").is_err());
+
+        // A pawn only captures with its file and the `x`
+        let b = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert!(b.parse_san("exd5").unwrap().from == Square::E4);
+        let err = b.parse_san("d5");
+        assert!(matches!(err, Err(InvalidMove(msg)) if msg.contains("no legal move")));
+        assert!(b.parse_san("xd5").is_err());
+        let b = Board::from_fen("4k3/8/8/3p4/2P1P3/8/8/4K3 w - - 0 1").unwrap();
+        assert!(b.parse_san("cxd5").unwrap().from == Square::C4);
+        let err = b.parse_san("d5");
+        assert!(matches!(err, Err(InvalidMove(msg)) if msg.contains("no legal move")));
+
+        // Promotions, with and without capture
+        let b = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let m = b.parse_san("a8=Q").unwrap();
+        assert!(m.promoting_piece == Some(Kind::Queen));
+        let m = b.parse_san("axb8=N+").unwrap();
+        assert!(m.promoting_piece == Some(Kind::Knight) && m.to == Square::B8);
+        assert!(b.parse_san("a8N").unwrap().promoting_piece == Some(Kind::Knight));
+        assert!(b.parse_san("a8").is_err());
+        assert!(b.parse_san("a8=K").is_err());
+    }
+
+    #[test]
+    fn test_parse_san_disambiguation() {
+        // Knights on b1 and f3 can both reach d2
+        let b = Board::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert!(b.parse_san("Nbd2").unwrap().from == Square::B1);
+        assert!(b.parse_san("Nfd2").unwrap().from == Square::F3);
+        assert!(matches!(b.parse_san("Nd2"), Err(InvalidMove(msg)) if msg.contains("ambiguous")));
+
+        // Rooks on e1 and e3, same file
+        let b = Board::from_fen("4k3/8/8/8/8/4R3/8/K3R3 w - - 0 1").unwrap();
+        assert!(b.parse_san("R1e2").unwrap().from == Square::E1);
+        assert!(b.parse_san("R3e2").unwrap().from == Square::E3);
+        assert!(b.parse_san("Re2").is_err());
+
+        // Queens on h4, e4 and h1 can all reach e1, so h4 needs both hints
+        let b = Board::from_fen("k7/8/8/8/4Q2Q/8/8/K6Q w - - 0 1").unwrap();
+        assert!(b.parse_san("Qh4e1").unwrap().from == Square::H4);
+        assert!(b.parse_san("Qee1").unwrap().from == Square::E4);
+        assert!(b.parse_san("Q1e1").unwrap().from == Square::H1);
+        assert!(b.parse_san("Qhe1").is_err());
+        assert!(b.parse_san("Q4e1").is_err());
+        assert!(b.parse_san("Qa4e1").is_err());
+    }
+
+    #[test]
+    fn test_is_theoretical_draw() {
+        for fen in [
+            "7k/8/8/8/8/8/8/K7 w - - 0 1",
+            "7k/8/8/8/8/8/8/KNN5 w - - 0 1",
+            "1nn4k/8/8/8/8/8/8/K7 b - - 0 1",
+        ] {
+            assert!(Board::from_fen(fen).unwrap().is_theoretical_draw(), "{fen}");
+        }
+        for fen in [
+            "7k/8/8/8/8/8/8/KBN5 w - - 0 1",
+            "7k/8/8/8/8/8/8/KNNN4 w - - 0 1",
+            "6nk/8/8/8/8/8/8/KNN5 w - - 0 1",
+            "7k/8/8/8/8/8/8/KR6 w - - 0 1",
+        ] {
+            assert!(
+                !Board::from_fen(fen).unwrap().is_theoretical_draw(),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(Board::default().status(), GameStatus::Ongoing);
+
+        let b = Board::from_fen("k6b/Q7/8/8/8/8/8/R3K3 b Q - 0 1").unwrap();
+        assert_eq!(b.status(), GameStatus::Checkmate(Color::Black));
+
+        let b = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(b.status(), GameStatus::Stalemate);
+
+        // In check, but with a way out
+        let b = Board::from_fen("k7/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert_eq!(b.status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_fifty_move_draw() {
+        let b = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert!(!b.is_fifty_move_draw());
+        assert_eq!(b.status(), GameStatus::Ongoing);
+
+        // A quiet rook move reaches 100 plies
+        let mut after = b.clone();
+        play(&mut after, "a1a2");
+        assert!(after.is_fifty_move_draw());
+        assert_eq!(after.status(), GameStatus::FiftyMoveDraw);
+        assert_eq!(
+            after.game_over_reason(),
+            Some(GameResult::Draw(DrawReason::FiftyMove))
+        );
+
+        // Checkmate on the 100th ply is still a win
+        let b = Board::from_fen("4k3/R7/8/8/8/8/8/4K2R w - - 99 80").unwrap();
+        let mut after = b.clone();
+        play(&mut after, "h1h8");
+        assert!(after.is_fifty_move_draw());
+        assert_eq!(after.status(), GameStatus::Checkmate(Color::Black));
+
+        // A pawn move resets the clock
+        let mut b = Board::from_fen("4k3/8/8/8/8/8/P7/4K3 w - - 99 80").unwrap();
+        play(&mut b, "a2a3");
+        assert!(!b.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        // Castling, en passant, promotions with and without capture, and
+        // rook captures removing castling rights
+        let mut b = Board::from_fen("r3k2r/1P4P1/8/8/3p4/8/4P3/R3K2R w KQkq - 0 1").unwrap();
+        for uci in ["e2e4", "d4e3", "e1g1", "e3e2", "g7h8q", "e8e7", "b7a8q", "e2f1q"] {
+            play(&mut b, uci);
+            assert_eq!(b.hash, b.zobrist_hash());
+        }
+    }
+
+    #[test]
+    fn test_legal_moves_iterator() {
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ] {
+            let b = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&b);
+            mg.gen_legal_moves();
+            let moves: Vec<Move> = b.legal_moves().collect();
+            assert_eq!(&moves, mg.get_legal_moves());
+        }
+
+        let b =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(b.legal_moves().count(), 48);
+        assert_eq!(
+            b.legal_moves()
+                .filter(|m| m.captured_piece.is_some())
+                .count(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_shredder_fen() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let b = Board::from_fen(fen).unwrap();
+        assert!(b.chess960);
+        assert_eq!(b.castling_rook_files, [[7, 5]; 2]);
+        assert!(b.castling_rook_square(Color::Black, CastleSide::Queen) == Some(Square::F8));
+        assert_eq!(b.to_fen(), fen);
+
+        // X-FEN: KQkq refers to the outermost rooks
+        let b = Board::from_fen("rk2r3/8/8/8/8/8/8/RK2R3 w KQkq - 0 1").unwrap();
+        assert!(b.chess960);
+        assert_eq!(b.castling_rook_files, [[4, 0]; 2]);
+        assert_eq!(b.to_fen(), "rk2r3/8/8/8/8/8/8/RK2R3 w EAea - 0 1");
+
+        // Different rook files for each color, neither overwriting the other
+        let fen = "r3k1r1/8/8/8/8/8/8/R3K2R w HAga - 0 1";
+        let b = Board::from_fen(fen).unwrap();
+        assert_eq!(b.castling_rook_files, [[7, 0], [6, 0]]);
+        assert!(b.castling_rook_square(Color::White, CastleSide::King) == Some(Square::H1));
+        assert!(b.castling_rook_square(Color::Black, CastleSide::King) == Some(Square::G8));
+        assert_eq!(b.to_fen(), fen);
+
+        let b = Board::default();
+        assert!(!b.chess960);
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w X - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_castling_file_letters() {
+        let b = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap();
+        for color in [Color::White, Color::Black] {
+            for side in [CastleSide::King, CastleSide::Queen] {
+                assert!(b.casteling_rights.get(color, side));
+            }
+        }
+        assert_eq!(b.castling_rook_files, [[7, 0]; 2]);
+        // Written back the way it was given
+        assert_eq!(b.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1");
+        assert_eq!(
+            b.zobrist_hash(),
+            Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+                .unwrap()
+                .zobrist_hash()
+        );
+
+        // Mixing both notations, for a single side each
+        let b = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Ka - 0 1").unwrap();
+        assert!(b.casteling_rights.get(Color::White, CastleSide::King));
+        assert!(!b.casteling_rights.get(Color::White, CastleSide::Queen));
+        assert!(!b.casteling_rights.get(Color::Black, CastleSide::King));
+        assert!(b.casteling_rights.get(Color::Black, CastleSide::Queen));
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        // e5 is attacked by the d4 pawn, the c6 knight, the b8 queen, the e1
+        // rook and the f4 king, but not by the f6 pawn which moves the other
+        // way, nor the h5 rook behind the g5 knight
+        let b = Board::from_fen("1Q6/8/2N2P2/k5Nr/3P1K2/8/8/4R3 w - - 0 1").unwrap();
+        let expected = [Square::D4, Square::C6, Square::B8, Square::E1, Square::F4]
+            .iter()
+            .fold(Bitboard(0), |acc, &sq| acc | square_mask(sq));
+        assert_eq!(b.attackers_to(Square::E5, Color::White), expected);
+        assert_eq!(b.attackers_to(Square::E5, Color::Black), Bitboard(0));
+        assert_eq!(
+            b.attackers_to(Square::G5, Color::Black),
+            square_mask(Square::H5)
+        );
+    }
+
+    #[test]
+    fn test_pinned() {
+        // Bishop pin on the d2 knight, rook pin on the e4 pawn
+        let b = Board::from_fen("4r2k/8/8/b7/4P3/8/3N4/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            b.pinned(Color::White),
+            Bitboard::from_squares(&[Square::D2, Square::E4])
+        );
+        assert_eq!(b.pinned(Color::Black), Bitboard(0));
+
+        // Two pieces between the king and the rook: neither is pinned
+        let b = Board::from_fen("4r2k/8/8/4P3/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(b.pinned(Color::White), Bitboard(0));
+
+        // An enemy piece in between, or a slider not moving along the line
+        let b = Board::from_fen("4b2k/8/8/8/4p3/8/3N4/r3K3 w - - 0 1").unwrap();
+        assert_eq!(b.pinned(Color::White), Bitboard(0));
+    }
+
+    #[test]
+    fn test_material_balance() {
+        let board = Board::default();
+        assert_eq!(board.material(Color::White), 4000);
+        assert_eq!(board.material(Color::Black), 4000);
+        assert_eq!(board.material_balance(), 0);
+
+        // Black is missing its queen
+        let board =
+            Board::from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(board.material(Color::Black), 3100);
+        assert_eq!(board.material_balance(), 900);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let board = Board::default();
+        assert_eq!(board.perft(5), 4_865_609);
+
+        let divide = board.perft_divide(5);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(
+            divide.iter().map(|(_, nodes)| nodes).sum::<u64>(),
+            4_865_609
+        );
+        let e2e4 = board.parse_uci("e2e4").unwrap();
+        assert!(divide.contains(&(e2e4, 405_385)));
+
+        assert_eq!(board.perft(0), 1);
+        assert!(board.perft_divide(0).is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_perft_parallel() {
+        let board = Board::default();
+        assert_eq!(board.perft_parallel(5), board.perft(5));
+        assert_eq!(board.perft_parallel(0), 1);
+
+        // Kiwipete, whose root moves have subtrees of very different sizes
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(board.perft_parallel(3), board.perft(3));
+
+        // Checkmated, no root move to split
+        let mated = Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(mated.perft_parallel(2), 0);
+    }
+
+    #[test]
+    fn test_board_eq_and_hash() {
+        let mut positions = FxHashSet::default();
+        positions.insert(Board::default());
+        assert!(positions.contains(&Board::from_fen(Board::STARTPOS_FEN).unwrap()));
+
+        // Same position with other move clocks
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let other = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 12 40";
+        assert!(Board::from_fen(fen).unwrap() == Board::from_fen(other).unwrap());
+
+        // Reached by playing moves
+        let mut board = Board::default();
+        for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            play(&mut board, uci);
+        }
+        assert!(positions.contains(&board));
+
+        let differs = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R2K3R w kq - 0 1",
+        ];
+        for fen in differs {
+            assert!(Board::from_fen(fen).unwrap() != Board::from_fen(other).unwrap());
+        }
+
+        // Chess960 rook files of lost castling rights do not count
+        let mut board =
+            Board::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        play(&mut board, "e1f3");
+        play(&mut board, "f8e8");
+        let reparsed = Board::from_fen(&board.to_fen()).unwrap();
+        assert_eq!(board.hash, reparsed.hash);
+        assert!(board == reparsed);
+    }
+
+    #[test]
+    fn test_en_passant_needs_a_capturing_pawn() {
+        // No black pawn next to e4
+        let b =
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert!(b.en_passant.is_none());
+        assert!(
+            b == Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+                .unwrap()
+        );
+
+        let b = Board::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        assert!(b.en_passant == Some(Square::E3));
+
+        // Same when the double push is played
+        let mut b = Board::default();
+        play(&mut b, "e2e4");
+        assert!(b.en_passant.is_none());
+        assert!(b.verify_hash());
+        play(&mut b, "d7d5");
+        play(&mut b, "e4e5");
+        play(&mut b, "f7f5");
+        assert!(b.en_passant == Some(Square::F6));
+        assert!(b.verify_hash());
+    }
+
+    #[test]
+    fn test_king_square() {
+        let b = Board::default();
+        assert_eq!(b.king_square(Color::White), Square::E1);
+        assert_eq!(b.king_square(Color::Black), Square::E8);
+
+        let b = Board::from_fen("8/8/8/K1Pp3r/8/8/8/k7 w - - 0 1").unwrap();
+        assert_eq!(b.king_square(Color::White), Square::A5);
+        assert_eq!(b.king_square(Color::Black), Square::A1);
+
+        let b = Board::from_fen("7K/8/8/8/8/8/8/6k1 b - - 0 1").unwrap();
+        assert_eq!(b.king_square(Color::White), Square::H8);
+        assert_eq!(b.king_square(Color::Black), Square::G1);
+    }
+
+    #[test]
+    fn test_checkers() {
+        assert!(Board::default().checkers() == 0);
+
+        // The bishop on b4 checks the king on e1
+        let b = Board::from_fen("4k3/8/8/8/1b6/8/8/4K3 w - - 0 1").unwrap();
+        assert!(b.checkers() == square_mask(Square::B4));
+
+        // Double check from the rook on e8 and the knight on f3
+        let b = Board::from_fen("4r1k1/8/8/8/8/5n2/8/4K3 w - - 0 1").unwrap();
+        assert!(b.checkers() == Bitboard::from_squares(&[Square::E8, Square::F3]));
+        assert_eq!(b.checkers().count_ones(), 2);
+        assert!(b.legal_moves().all(|m| m.piece_kind == Kind::King));
+    }
+
+    #[test]
+    fn test_attacked_squares() {
+        let b = Board::from_fen("4k3/8/8/3p4/4N3/8/8/1B2K2R w K - 0 1").unwrap();
+        let white = b.attacked_squares(Color::White);
+        // Knight, bishop up to the knight it defends, rook and king
+        for square in [
+            Square::D6,
+            Square::F6,
+            Square::C3,
+            Square::A2,
+            Square::D3,
+            Square::E4,
+            Square::H8,
+            Square::F1,
+            Square::D2,
+        ] {
+            assert!(white & square_mask(square) != 0, "{square}");
+        }
+        for square in [Square::F5, Square::E5, Square::A8, Square::E3, Square::A1] {
+            assert!(white & square_mask(square) == 0, "{square}");
+        }
+
+        let black = b.attacked_squares(Color::Black);
+        for square in [Square::C4, Square::E4, Square::D8, Square::F7] {
+            assert!(black & square_mask(square) != 0, "{square}");
+        }
+        assert!(black & square_mask(Square::D4) == 0);
+
+        // Agrees with the attacker lookup on every square
+        for square in Square::iter() {
+            for color in [Color::White, Color::Black] {
+                assert_eq!(
+                    b.attacked_squares(color) & square_mask(square) != 0,
+                    b.is_square_attacked(square, color)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_board_piece_by_piece() {
+        let mut b = Board::empty();
+        assert!(b.all_pieces() == 0);
+        assert!(b.verify_hash());
+
+        b.set_piece(Square::E1, Kind::King, Color::White);
+        b.set_piece(Square::A1, Kind::Rook, Color::White);
+        b.set_piece(Square::E8, Kind::King, Color::Black);
+        // Replaced by the rook below
+        b.set_piece(Square::H8, Kind::Queen, Color::Black);
+        b.set_piece(Square::H8, Kind::Rook, Color::White);
+        assert!(b.black_queen.bitboard == 0);
+
+        assert_eq!(b.remove_piece(Square::H8), Some((Kind::Rook, Color::White)));
+        assert_eq!(b.remove_piece(Square::H8), None);
+
+        assert_eq!(b.to_fen(), "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert!(b == Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap());
+        assert!(b.verify_hash());
+        // 5 king moves and 10 rook moves
+        assert_eq!(b.legal_move_count(), 15);
+    }
+
+    #[test]
+    fn test_piece_at() {
+        let b = Board::default();
+        assert_eq!(b.piece_at(Square::A1), Some((Color::White, Kind::Rook)));
+        assert_eq!(b.piece_at(Square::E7), Some((Color::Black, Kind::Pawn)));
+        assert_eq!(b.piece_at(Square::D8), Some((Color::Black, Kind::Queen)));
+        assert_eq!(b.piece_at(Square::E4), None);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let board: Board = Board::STARTPOS_FEN.parse().unwrap();
+        assert!(board == Board::default());
+        assert!("not a fen".parse::<Board>().is_err());
+    }
+
+    #[test]
+    fn test_display_from() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(board.display_from(Color::White), board.to_string());
+
+        let white = board.display_from(Color::White);
+        let black = board.display_from(Color::Black);
+        let white_lines: Vec<&str> = white.lines().collect();
+        let black_lines: Vec<&str> = black.lines().collect();
+        assert_eq!(white_lines[0], "8 . . . . ♚ . . . ");
+        assert_eq!(white_lines[7], "1 ♖ . . . ♔ . . . ");
+        assert_eq!(white_lines[8], "  a b c d e f g h");
+        assert_eq!(black_lines[0], "1 . . . ♔ . . . ♖ ");
+        assert_eq!(black_lines[7], "8 . . . ♚ . . . . ");
+        assert_eq!(black_lines[8], "  h g f e d c b a");
+    }
+
+    #[test]
+    fn test_display_ascii() {
+        assert_eq!(
+            Piece::create_initial(Kind::Knight, Color::White).get_ascii_char(),
+            'N'
+        );
+        assert_eq!(
+            Piece::create_initial(Kind::Queen, Color::Black).get_ascii_char(),
+            'q'
+        );
+
+        let board = Board::default();
+        let ascii = board.display_ascii_from(Color::White);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines[0], "8 r n b q k b n r ");
+        assert_eq!(lines[7], "1 R N B Q K B N R ");
+        assert!(ascii.is_ascii());
+        assert_eq!(
+            board.display_ascii_from(Color::Black).lines().next(),
+            Some("1 R N B K Q B N R ")
+        );
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let config = bincode::config::standard();
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let bytes = bincode::serde::encode_to_vec(&board, config).unwrap();
+            let (decoded, _): (Board, usize) =
+                bincode::serde::decode_from_slice(&bytes, config).unwrap();
+            assert!(decoded == board);
+            assert_eq!(decoded.hash, board.hash);
+            assert_eq!(decoded.to_fen(), fen);
+        }
+
+        // The starting position through JSON
+        let board = Board::default();
+        let json = serde_json::to_string(&board).unwrap();
+        let decoded: Board = serde_json::from_str(&json).unwrap();
+        assert!(decoded == board);
+        assert_eq!(decoded.hash, board.hash);
+        assert_eq!(decoded.to_fen(), Board::STARTPOS_FEN);
+    }
+
+    #[test]
+    fn test_array_round_trip() {
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1")
+                .unwrap();
+        let array = board.to_array();
+        assert_eq!(array[0][0], Some((Color::White, Kind::Rook)));
+        assert_eq!(array[0][4], Some((Color::White, Kind::King)));
+        assert_eq!(array[4][3], Some((Color::White, Kind::Pawn)));
+        assert_eq!(array[6][4], Some((Color::Black, Kind::Queen)));
+        assert_eq!(array[3][0], None);
+
+        let rebuilt = Board::from_array(&array);
+        assert!(rebuilt == board);
+        assert_eq!(rebuilt.hash, board.hash);
+        assert_eq!(rebuilt.to_array(), array);
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut board = Board::default();
+        play(&mut board, "e2e4");
+        play(&mut board, "a7a6");
+        play(&mut board, "e4e5");
+        play(&mut board, "d7d5");
+        assert_eq!(board.en_passant, Some(Square::D6));
+        let before = board.clone();
+
+        let undo = board.make_null_move();
+        assert_eq!(board.to_move, Color::Black);
+        assert_eq!(board.en_passant, None);
+        assert!(board.casteling_rights == before.casteling_rights);
+        assert_eq!(board.halfmove_clock, before.halfmove_clock);
+        assert!(board.verify_hash());
+        assert_ne!(board.hash, before.hash);
+
+        // Moves can be played and taken back on top of the null move
+        let m = play(&mut board, "g8f6");
+        board.undo_move(&m);
+
+        board.unmake_null_move(undo);
+        assert!(board == before);
+        assert_eq!(board.hash, before.hash);
+        assert_eq!(board.to_fen(), before.to_fen());
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        let board = Board::from_fen("4k3/1p6/4p3/2P4P/5P2/2P5/P5P1/4K3 w - - 0 1").unwrap();
+        let squares = |bitboard: Bitboard| bitboard.iter_squares().collect::<Vec<_>>();
+
+        assert_eq!(
+            squares(board.passed_pawns(Color::White)),
+            [Square::G2, Square::H5]
+        );
+        assert_eq!(
+            squares(board.isolated_pawns(Color::White)),
+            [Square::A2, Square::C3, Square::C5]
+        );
+        assert_eq!(
+            squares(board.doubled_pawns(Color::White)),
+            [Square::C3, Square::C5]
+        );
+
+        assert_eq!(board.passed_pawns(Color::Black), 0);
+        assert_eq!(
+            squares(board.isolated_pawns(Color::Black)),
+            [Square::E6, Square::B7]
+        );
+        assert_eq!(board.doubled_pawns(Color::Black), 0);
+
+        // A black pawn is passed once the white pawns in front are gone
+        let board = Board::from_fen("4k3/8/8/8/3p4/8/P7/4K3 b - - 0 1").unwrap();
+        assert_eq!(squares(board.passed_pawns(Color::Black)), [Square::D4]);
+        assert_eq!(squares(board.passed_pawns(Color::White)), [Square::A2]);
+    }
+
+    #[test]
+    fn test_mobility() {
+        // A queen and a rook against a lone king
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R2QK3 w - - 0 1").unwrap();
+        assert_eq!(board.mobility(Color::Black), 5);
+        assert!(board.mobility(Color::White) > 4 * board.mobility(Color::Black));
+
+        // Mobility does not depend on the side to move
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            for color in [Color::White, Color::Black] {
+                let turn = board.with_side_to_move(color);
+                let mut mg = MoveGen::new(&turn);
+                mg.gen_pseudo_moves();
+                let expected = u32::try_from(mg.get_pseudo_moves().len()).unwrap();
+                assert_eq!(board.mobility(color), expected, "{fen}");
+            }
+        }
+
+        // The en passant capture only counts for the side to move
+        let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert_eq!(board.mobility(Color::White), 5 + 2);
+        assert_eq!(
+            board.with_side_to_move(Color::Black).mobility(Color::White),
+            5 + 1
+        );
+    }
+
+    #[test]
+    fn test_random_legal_move() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let legal: Vec<Move> = board.legal_moves().collect();
+        let pick = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| board.random_legal_move(&mut rng).unwrap())
+                .collect::<Vec<Move>>()
+        };
+        let picked = pick(42);
+        assert_eq!(picked, pick(42));
+        assert!(picked.iter().all(|m| legal.contains(m)));
+        assert!(picked.iter().any(|m| *m != picked[0]));
+
+        // Checkmate: no move to pick
+        let mated = Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(mated.random_legal_move(&mut StdRng::seed_from_u64(0)), None);
+    }
+
+    #[test]
+    fn test_is_legal() {
+        let board = Board::default();
+        let e4 = board.parse_uci("e2e4").unwrap();
+        assert!(board.is_legal(&e4));
+
+        // Wrong side: a black move with White to move
+        let e5 = Board::default()
+            .with_side_to_move(Color::Black)
+            .parse_uci("e7e5")
+            .unwrap();
+        assert!(!board.is_legal(&e5));
+
+        // Same squares but wrong flags
+        let mut not_double = e4.clone();
+        not_double.double_push = false;
+        assert!(!board.is_legal(&not_double));
+
+        // Moving the pinned knight exposes the king
+        let board = Board::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        let knight = Move {
+            piece_kind: Kind::Knight,
+            piece_color: Color::White,
+            from: Square::E2,
+            to: Square::C3,
+            casteling: false,
+            promoting_piece: None,
+            double_push: false,
+            en_passant: false,
+            captured_piece: None,
+        };
+        assert!(!board.is_legal(&knight));
+        // The king may step to d1, unless a rook attacks it there
+        let king = Move {
+            piece_kind: Kind::King,
+            from: Square::E1,
+            to: Square::D1,
+            ..knight.clone()
+        };
+        assert!(board.is_legal(&king));
+        let board = Board::from_fen("4k3/3r4/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        assert!(!board.is_legal(&king));
+    }
+
+    #[test]
+    fn test_legal_moves_from() {
+        let board = Board::default();
+        let destinations: Vec<Square> = board
+            .legal_moves_from(Square::B1)
+            .iter()
+            .map(|m| m.to)
+            .collect();
+        assert_eq!(destinations, [Square::A3, Square::C3]);
+        assert!(board.legal_moves_from(Square::E4).is_empty());
+        assert!(board.legal_moves_from(Square::B8).is_empty());
+        assert_eq!(board.legal_moves_from(Square::E2).len(), 2);
+    }
+
+    #[test]
+    fn test_count_legal_moves() {
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1",
+            "4k3/8/8/8/1b6/8/8/RN2K3 w Q - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            assert_eq!(
+                board.count_legal_moves(),
+                mg.get_legal_moves().len(),
+                "{fen}"
+            );
+
+            // Nothing is collected while counting
+            let mut mg = MoveGen::new(&board);
+            mg.count_legal_moves();
+            assert!(mg.get_pseudo_moves().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_from_epd() {
+        let (board, operations) = Board::from_epd(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#,
+        )
+        .unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(operations["id"], "WAC.001");
+        assert_eq!(operations["bm"], "Qg6");
+        assert_eq!(operations.len(), 2);
+
+        // Several moves, and a `;` inside a string operand
+        let (_, operations) = Board::from_epd(
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4; am f3; c0 "a; b";"#,
+        )
+        .unwrap();
+        assert_eq!(operations["bm"], "e4 d4");
+        assert_eq!(operations["am"], "f3");
+        assert_eq!(operations["c0"], "a; b");
+
+        assert!(Board::from_epd("8/8/8 w - -").is_err());
+    }
+
+    #[test]
+    fn test_gives_check() {
+        let gives_check = |fen: &str, uci: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            board.gives_check(&board.parse_uci(uci).unwrap())
+        };
+
+        // Direct check, and a quiet move
+        let fen = "4k3/8/8/8/8/8/8/1B2K3 w - - 0 1";
+        assert!(gives_check(fen, "b1g6"));
+        assert!(!gives_check(fen, "b1c2"));
+        // Discovered check by the rook behind the knight
+        let fen = "4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1";
+        assert!(gives_check(fen, "e4c3"));
+        // Promotion to a piece that checks, not to one that does not
+        let fen = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        assert!(gives_check(fen, "b7b8q"));
+        assert!(!gives_check(fen, "b7b8n"));
+        // Castling rook check
+        assert!(gives_check("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"));
+        // En passant uncovering the bishop behind the captured pawn
+        assert!(gives_check("7k/8/b7/8/1pP5/8/8/5K2 b - c3 0 1", "b4c3"));
+
+        // Agrees with playing the move on every legal move
+        for fen in [
+            Board::STARTPOS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            for m in board.legal_moves() {
+                let mut after = board.clone();
+                after.do_move(&m);
+                let check = after.is_in_check(after.to_move);
+                assert_eq!(board.gives_check(&m), check, "{fen} {}", m.to_uci());
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_moves() {
+        let round_trip = |fen: &str, uci: &str, flag: u16| {
+            let board = Board::from_fen(fen).unwrap();
+            let m = board.parse_uci(uci).unwrap();
+            let compact = m.to_compact();
+            assert_eq!(compact >> 12, flag, "{uci}");
+            assert_eq!(compact & 0x3F, m.from as u16);
+            assert_eq!((compact >> 6) & 0x3F, m.to as u16);
+            assert_eq!(board.move_from_compact(compact), Some(m));
+        };
+
+        round_trip(Board::STARTPOS_FEN, "g1f3", 0);
+        round_trip(Board::STARTPOS_FEN, "e2e4", 1);
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        round_trip(kiwipete, "e1g1", 2);
+        round_trip(kiwipete, "e1c1", 3);
+        round_trip(kiwipete, "e5f7", 4);
+        round_trip("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 5);
+        let promotions = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        round_trip(promotions, "a7a8n", 8);
+        round_trip(promotions, "a7a8q", 11);
+        round_trip(promotions, "a7b8r", 14);
+
+        // Every legal move has its own code
+        let board = Board::from_fen(kiwipete).unwrap();
+        let codes: FxHashSet<u16> = board.legal_moves().map(|m| m.to_compact()).collect();
+        assert_eq!(codes.len(), board.legal_move_count());
+
+        // Not legal here
+        let e4 = Board::default().parse_uci("e2e4").unwrap().to_compact();
+        assert_eq!(board.move_from_compact(e4), None);
+    }
+
+    #[test]
+    fn test_unmake_white_queenside_castling() {
+        // Undoing O-O-O used to keep the rook on d1 and drop the others
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        let m = mg
+            .get_legal_moves()
+            .iter()
+            .find(|m| m.casteling && m.to == Square::C1)
+            .unwrap()
+            .clone();
+        let undo = board.make_move(&m);
+        assert!(board.white_rook.bitboard == Bitboard::from_squares(&[Square::D1, Square::H1]));
+        board.unmake_move(&m, undo);
+        assert!(board.white_rook.bitboard == Bitboard::from_squares(&[Square::A1, Square::H1]));
+        assert_eq!(board.to_fen(), fen);
     }
 }
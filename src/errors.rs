@@ -2,4 +2,5 @@
 pub enum ChessMgError {
     InvalidFEN(String),
     InvalidSquare,
+    InvalidMove(String),
 }
@@ -0,0 +1,316 @@
+//! This module provides the `Game` struct, a `Board` along with the
+//! positions it went through, needed for draws by repetition.
+use crate::board::{Board, DrawReason, GameResult};
+use crate::errors::ChessMgError;
+use crate::errors::ChessMgError::InvalidMove;
+use crate::move_gen::Move;
+use crate::utils::Color;
+
+pub struct Game {
+    board: Board,
+    // Position the game started from, to replay the moves
+    start: Board,
+    // Moves played since `start`
+    moves: Vec<Move>,
+    // Repetition keys of every position reached, the current one included
+    history: Vec<u64>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Board::default())
+    }
+}
+
+impl Game {
+    /// Starts a game from `board`.
+    pub fn new(board: Board) -> Self {
+        let history = vec![board.repetition_key()];
+        Self {
+            start: board.clone(),
+            board,
+            moves: Vec::new(),
+            history,
+        }
+    }
+
+    /// Replays the PGN movetext `pgn` from the starting position.
+    /// Move numbers, `{}` comments, `[]` tag pairs, `()` variations, `$`
+    /// annotations and the result token are skipped.
+    ///
+    /// # Errors
+    /// Returns `InvalidMove`, with the index of the move starting at 1, if
+    /// a move is malformed or not legal, or if a variation is not closed.
+    pub fn from_pgn(pgn: &str) -> Result<Game, ChessMgError> {
+        let movetext = main_line(pgn)?;
+        let mut game = Game::default();
+        let sans = movetext
+            .split_whitespace()
+            .map(strip_move_number)
+            .filter(|token| {
+                !token.is_empty()
+                    && !token.starts_with('$')
+                    && !["1-0", "0-1", "1/2-1/2", "*"].contains(token)
+            });
+        for (index, san) in sans.enumerate() {
+            let m = game.board.parse_san(san).map_err(|err| match err {
+                InvalidMove(reason) => InvalidMove(format!("Move {}: {reason}", index + 1)),
+                err => err,
+            })?;
+            game.play(&m);
+        }
+        Ok(game)
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Plays a move, which must be legal in the current position.
+    pub fn play(&mut self, m: &Move) {
+        self.board.do_move(m);
+        self.moves.push(m.clone());
+        self.history.push(self.board.repetition_key());
+    }
+
+    /// The moves played since the start of the game.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// The PGN movetext of the game (`1. e4 e5 2. Nf3 ...`), followed by
+    /// the result token: `1-0`, `0-1`, `1/2-1/2` or `*` if the game goes on.
+    pub fn to_pgn(&self) -> String {
+        let mut tokens = Vec::new();
+        let mut board = self.start.clone();
+        for (i, m) in self.moves.iter().enumerate() {
+            match board.to_move {
+                Color::White => tokens.push(format!("{}.", board.fullmove_number)),
+                // A game starting with Black to move opens with `1...`
+                Color::Black if i == 0 => tokens.push(format!("{}...", board.fullmove_number)),
+                Color::Black => {}
+            }
+            tokens.push(m.to_san(&board));
+            board.do_move(m);
+        }
+        let result = match self.result() {
+            GameResult::Ongoing => "*",
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        };
+        tokens.push(result.to_string());
+        tokens.join(" ")
+    }
+
+    /// Whether the current position occurred at least three times, with
+    /// the same side to move, castling rights and en passant square.
+    pub fn is_threefold_repetition(&self) -> bool {
+        let key = self.board.repetition_key();
+        self.history.iter().filter(|&&k| k == key).count() >= 3
+    }
+
+    /// The result of the game: a checkmate, a draw by stalemate,
+    /// threefold repetition, the fifty-move rule or insufficient material,
+    /// or `Ongoing`.
+    pub fn result(&self) -> GameResult {
+        match self.board.game_over_reason() {
+            Some(result) => result,
+            None if self.is_threefold_repetition() => GameResult::Draw(DrawReason::Threefold),
+            None => GameResult::Ongoing,
+        }
+    }
+}
+
+/// The movetext of `pgn` with the comments, tag pairs and variations
+/// blanked out, as they may contain spaces and moves of their own.
+fn main_line(pgn: &str) -> Result<String, ChessMgError> {
+    // End of the comment or tag pair being skipped, which do not nest
+    let mut closing = None;
+    // Variations nest, and may hold comments
+    let mut variations = 0_usize;
+    let mut movetext = String::with_capacity(pgn.len());
+    for c in pgn.chars() {
+        match (closing, c) {
+            (Some(end), c) if c == end => closing = None,
+            (None, '{') => closing = Some('}'),
+            (None, '[') => closing = Some(']'),
+            (None, '(') => variations += 1,
+            (None, ')') => {
+                variations = variations.checked_sub(1).ok_or_else(|| {
+                    InvalidMove("Unexpected ')' outside of a variation".to_string())
+                })?;
+            }
+            (None, c) if variations == 0 => {
+                movetext.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        movetext.push(' ');
+    }
+    if variations > 0 {
+        return Err(InvalidMove("Unterminated variation".to_string()));
+    }
+    Ok(movetext)
+}
+
+/// The move of a token, after its move number if any: `12.e4` and
+/// `12...e5` hold a move, `12.` and `12...` an empty one.
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < token.len() && rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, uci: &str) {
+        let m = game.board().parse_uci(uci).expect("Move is not legal");
+        game.play(&m);
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let mut game = Game::default();
+        for _ in 0..2 {
+            assert!(!game.is_threefold_repetition());
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                play(&mut game, uci);
+            }
+        }
+        // Back to the starting position for the third time
+        assert!(game.is_threefold_repetition());
+        play(&mut game, "b1c3");
+        assert!(!game.is_threefold_repetition());
+    }
+
+    #[test]
+    fn test_repetition_needs_same_rights() {
+        // The kings shuffle back and forth, but moving them first loses
+        // the castling rights, so the first position is not repeated
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let mut game = Game::new(board);
+        for _ in 0..3 {
+            for uci in ["e1f1", "e8f8", "f1e1", "f8e8"] {
+                play(&mut game, uci);
+            }
+        }
+        // Same placement as the start, seen 3 times without the castling
+        // rights and once with them
+        assert!(game.is_threefold_repetition());
+        let first = game.history[0];
+        assert_eq!(game.history.iter().filter(|&&k| k == first).count(), 1);
+    }
+
+    #[test]
+    fn test_to_pgn() {
+        // Scholar's mate
+        let mut game = Game::default();
+        assert_eq!(game.to_pgn(), "*");
+        for uci in ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"] {
+            play(&mut game, uci);
+        }
+        assert_eq!(game.to_pgn(), "1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 *");
+        play(&mut game, "h5f7");
+        assert_eq!(game.moves().len(), 7);
+        assert_eq!(game.to_pgn(), "1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0");
+
+        // Starting with Black to move
+        let board = Board::from_fen("4k3/8/8/8/8/8/7q/R3K3 b Q - 0 30").unwrap();
+        let mut game = Game::new(board);
+        for uci in ["h2h1", "e1e2", "e8d7"] {
+            play(&mut game, uci);
+        }
+        assert_eq!(game.to_pgn(), "30... Qh1+ 31. Ke2 Kd7 *");
+    }
+
+    #[test]
+    fn test_from_pgn() {
+        let game =
+            Game::from_pgn("[Event \"Test\"]\n1. e4 e5 {King's pawn} 2. Nf3 Nc6 3.Bb5 $1 3...a6 *")
+                .unwrap();
+        assert_eq!(game.moves().len(), 6);
+        assert_eq!(
+            game.board().to_fen(),
+            "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4"
+        );
+
+        // Castling written with zeros, and a round trip through `to_pgn`
+        let game = Game::from_pgn("1. e4 e5 2. Nf3 Nf6 3. Bc4 Bc5 4. 0-0 O-O").unwrap();
+        assert_eq!(
+            Game::from_pgn(&game.to_pgn()).unwrap().board().to_fen(),
+            game.board().to_fen()
+        );
+        assert_eq!(game.to_pgn(), "1. e4 e5 2. Nf3 Nf6 3. Bc4 Bc5 4. O-O O-O *");
+
+        // The third move is not legal
+        match Game::from_pgn("1. e4 e5 2. Ke3") {
+            Err(InvalidMove(reason)) => assert!(reason.starts_with("Move 3:"), "{reason}"),
+            _ => panic!("Ke3 should not be legal"),
+        }
+
+        // Variations are skipped, nested or holding comments
+        let game =
+            Game::from_pgn("1. e4 (1. d4 d5 (1... Nf6) {a (note} 2. c4) 1... e5 2. Nf3").unwrap();
+        assert_eq!(game.to_pgn(), "1. e4 e5 2. Nf3 *");
+        assert!(matches!(
+            Game::from_pgn("1. e4 (1. d4 e5"),
+            Err(InvalidMove(reason)) if reason == "Unterminated variation"
+        ));
+        assert!(matches!(Game::from_pgn("1. e4 e5)"), Err(InvalidMove(_))));
+
+        // Only a leading move number is stripped, a stray dot is an error
+        match Game::from_pgn("1. e4. e5") {
+            Err(InvalidMove(reason)) => assert!(reason.starts_with("Move 1:"), "{reason}"),
+            _ => panic!("e4. should not be accepted"),
+        }
+    }
+
+    #[test]
+    fn test_result() {
+        // Fool's mate
+        let mut game = Game::default();
+        for uci in ["f2f3", "e7e5", "g2g4"] {
+            play(&mut game, uci);
+            assert_eq!(game.result(), GameResult::Ongoing);
+        }
+        play(&mut game, "d8h4");
+        assert_eq!(game.result(), GameResult::BlackWins);
+
+        // Stalemate
+        let mut game = Game::new(Board::from_fen("7k/8/5QK1/8/8/8/8/8 w - - 0 1").unwrap());
+        play(&mut game, "f6f7");
+        assert_eq!(game.result(), GameResult::Draw(DrawReason::Stalemate));
+
+        // Threefold repetition
+        let mut game = Game::default();
+        for _ in 0..2 {
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                play(&mut game, uci);
+            }
+        }
+        assert_eq!(game.result(), GameResult::Draw(DrawReason::Threefold));
+
+        // Fifty-move rule
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        let mut game = Game::new(board);
+        play(&mut game, "a1a2");
+        assert_eq!(game.result(), GameResult::Draw(DrawReason::FiftyMove));
+
+        // Insufficient material once the last rook is taken
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/3rK3 w - - 0 1").unwrap();
+        let mut game = Game::new(board);
+        play(&mut game, "e1d1");
+        assert_eq!(
+            game.result(),
+            GameResult::Draw(DrawReason::InsufficientMaterial)
+        );
+        assert_eq!(game.to_pgn(), "1. Kxd1 1/2-1/2");
+    }
+}
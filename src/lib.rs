@@ -3,12 +3,19 @@
 mod bitboard;
 pub mod board;
 mod errors;
+mod game;
 mod magic;
 mod move_gen;
 mod piece;
+mod position_set;
 mod utils;
+mod zobrist;
 
-pub use board::Board;
-pub use magic::load_magics;
-pub use move_gen::{Move, MoveGen};
-pub use utils::{Color, Kind, Square};
+pub use board::{Board, DrawReason, GameResult, GameStatus};
+pub use game::Game;
+pub use magic::{
+    classical_bishop_attacks, classical_rook_attacks, load_magics, load_magics_from, pext_available,
+};
+pub use move_gen::{Move, MoveGen, MoveType, StagedMoveGen};
+pub use position_set::PositionSet;
+pub use utils::{Casteling, CastleSide, Color, Kind, Square};
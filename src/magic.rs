@@ -4,28 +4,57 @@ use crate::utils::{
     SOUTH_WEST_RAY, WEST_RAY,
 };
 use rand::random;
-use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use std::array::from_fn;
 use std::fs;
-use std::path::Path;
-use std::sync::LazyLock;
-
-type MagicIndex = u16;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, OnceLock};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(
+    clippy::unsafe_derive_deserialize,
+    reason = "`mask`, which enables the unsafe PEXT lookup, is never deserialized"
+)]
 pub struct MagicEntry {
-    /// Maps magic indices to precomputed attack bitboards.
-    pub attack_set: FxHashMap<MagicIndex, Bitboard>,
-
-    /// The default attack set when there are no blockers.
-    pub default_attack: Bitboard,
+    /// Precomputed attack bitboards, indexed by magic index, or by
+    /// `pext(blockers, mask)` once reordered by `with_pext`. Holds
+    /// `1 << (64 - shift)` entries, one per possible index.
+    pub attack_set: Vec<Bitboard>,
 
     /// The magic number used for hashing blocker configurations.
     pub magic: u64,
 
     /// The number of bits to shift after multiplying by magic.
     pub shift: u8,
+
+    /// The blockers that matter for the square, set by `with_pext` when
+    /// `attack_set` is indexed with PEXT, empty otherwise.
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    #[serde(skip)]
+    pub mask: Bitboard,
+}
+
+/// The pieces with magic tables
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum SliderKind {
+    Rook,
+    Bishop,
+}
+
+impl SliderKind {
+    fn kind(self) -> Kind {
+        match self {
+            SliderKind::Rook => Kind::Rook,
+            SliderKind::Bishop => Kind::Bishop,
+        }
+    }
+
+    /// The squares whose occupancy matters for the attacks from `square`
+    fn mask(self, square: Square) -> Bitboard {
+        match self {
+            SliderKind::Rook => generate_rook_attack_mask(square),
+            SliderKind::Bishop => generate_bishop_attack_mask(square),
+        }
+    }
 }
 
 // TODO: figure out where this function belongs
@@ -72,7 +101,7 @@ pub fn enumerate_blockers(mask: Bitboard) -> Vec<Bitboard> {
 
 pub fn compute_attack(square: Square, blockers: Bitboard, kind: Kind) -> Bitboard {
     let mut attacks = Bitboard(0);
-    let (rank, file) = (square as u8 / 8, square as u8 % 8);
+    let (rank, file) = (square.rank(), square.file());
 
     let directions: &[(i32, i32)] = match kind {
         Kind::Rook => &[(-1, 0), (1, 0), (0, -1), (0, 1)], // vertical, horizontal
@@ -86,7 +115,7 @@ pub fn compute_attack(square: Square, blockers: Bitboard, kind: Kind) -> Bitboar
 
         while (0..8).contains(&r) && (0..8).contains(&f) {
             let sq = usize::try_from(r * 8 + f).unwrap();
-            attacks = attacks | Bitboard(1u64 << sq);
+            attacks |= Bitboard(1u64 << sq);
             if (blockers >> sq) & 1 != 0 {
                 break; // ray blocked
             }
@@ -98,59 +127,182 @@ pub fn compute_attack(square: Square, blockers: Bitboard, kind: Kind) -> Bitboar
     attacks
 }
 
+/// Slider attacks along `rays`, computed by walking each ray up to the first
+/// piece of `occupancy`. `increasing` tells, for each ray, whether its
+/// squares have a greater index than the origin.
+fn classical_attacks(
+    square: Square,
+    occupancy: Bitboard,
+    rays: [&[Bitboard; 64]; 4],
+    increasing: [bool; 4],
+) -> Bitboard {
+    let square = square as usize;
+    let mut attacks = Bitboard(0);
+    for (ray, increasing) in rays.into_iter().zip(increasing) {
+        attacks |= ray[square];
+        let blockers = (ray[square] & occupancy).0;
+        if blockers != 0 {
+            let blocker = if increasing {
+                blockers.trailing_zeros()
+            } else {
+                blockers.ilog2()
+            };
+            attacks &= !ray[blocker as usize];
+        }
+    }
+    attacks
+}
+
+/// Rook attacks computed without the magic tables, by walking the rays.
+/// Slower than `ROOK_MAGICS`, but useful as a fallback and to check them.
+pub fn classical_rook_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    classical_attacks(
+        square,
+        occupancy,
+        [&NORTH_RAY, &EAST_RAY, &SOUTH_RAY, &WEST_RAY],
+        [true, true, false, false],
+    )
+}
+
+/// Bishop attacks computed without the magic tables, by walking the rays.
+/// Slower than `BISHOP_MAGICS`, but useful as a fallback and to check them.
+pub fn classical_bishop_attacks(square: Square, occupancy: Bitboard) -> Bitboard {
+    classical_attacks(
+        square,
+        occupancy,
+        [
+            &NORTH_EAST_RAY,
+            &NORTH_WEST_RAY,
+            &SOUTH_EAST_RAY,
+            &SOUTH_WEST_RAY,
+        ],
+        [true, true, false, false],
+    )
+}
+
+/// Blocker patterns a loaded entry is checked against, restricted to its
+/// mask: none, all of them, and subsets spread over the board
+const SPOT_CHECKS: [u64; 8] = [
+    0,
+    u64::MAX,
+    0x5555_5555_5555_5555,
+    0xAAAA_AAAA_AAAA_AAAA,
+    0x0F0F_0F0F_0F0F_0F0F,
+    0xF0F0_F0F0_F0F0_F0F0,
+    0x00FF_00FF_00FF_00FF,
+    0x0123_4567_89AB_CDEF,
+];
+
 impl MagicEntry {
     // TODO: impl mul on &Bitbloard to avoid Copying
-    // TODO: Test function
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
+    #[allow(clippy::inline_always)]
     #[inline(always)]
     pub fn find_attack(&self, blockers: Bitboard) -> Bitboard {
-        let magic_index = u16::try_from((blockers.wrapping_mul(self.magic)) >> self.shift).unwrap();
-        *self
-            .attack_set
-            .get(&magic_index)
-            .unwrap_or(&self.default_attack)
+        #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+        if self.mask != 0 {
+            // SAFETY: `mask` is only set once the CPU is known to have BMI2
+            return unsafe { self.find_pext_attack(blockers) };
+        }
+        self.find_magic_attack(blockers)
+    }
+
+    /// Same as `find_attack`, indexing `attack_set` with PEXT. Only inlined
+    /// when the crate itself is compiled with BMI2.
+    ///
+    /// # Safety
+    /// The CPU must support BMI2, and `attack_set` be reordered by
+    /// `with_pext`.
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    #[target_feature(enable = "bmi2")]
+    #[inline]
+    unsafe fn find_pext_attack(&self, blockers: Bitboard) -> Bitboard {
+        let index = std::arch::x86_64::_pext_u64(blockers.0, self.mask.0);
+        self.attack_set[usize::try_from(index).unwrap()]
+    }
+
+    /// Same as `find_attack`, always going through the magic number.
+    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    pub fn find_magic_attack(&self, blockers: Bitboard) -> Bitboard {
+        let magic_index = usize::try_from(blockers.wrapping_mul(self.magic) >> self.shift).unwrap();
+        self.attack_set[magic_index]
+    }
+
+    /// Reorders `attack_set` by PEXT index and sets `mask`, so that
+    /// `find_attack` uses PEXT. `enumerate_blockers` lists the blocker sets
+    /// of `mask` in the order of their PEXT index. The table keeps its
+    /// size, as it holds one entry per blocker set either way.
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    fn build_pext(&mut self, square: Square, slider: SliderKind) {
+        let mask = slider.mask(square);
+        self.attack_set = enumerate_blockers(mask)
+            .into_iter()
+            .map(|blockers| self.find_magic_attack(blockers))
+            .collect();
+        self.mask = mask;
+    }
+
+    /// Whether the entry, e.g. read from a file, fits `square`: its shift
+    /// matches the number of relevant blockers, `attack_set` holds one
+    /// bitboard per index, so that no lookup can go out of bounds, and the
+    /// blocker sets of `SPOT_CHECKS` are mapped to their attacks. Checking
+    /// every blocker set would cost about as much as generating the table.
+    fn is_valid(&self, square: Square, slider: SliderKind) -> bool {
+        let mask = slider.mask(square);
+        let bits = mask.count_ones();
+        u32::from(self.shift) == 64 - bits
+            && self.attack_set.len() == 1 << bits
+            && SPOT_CHECKS.iter().all(|&pattern| {
+                let blockers = mask & Bitboard(pattern);
+                self.find_magic_attack(blockers) == compute_attack(square, blockers, slider.kind())
+            })
     }
 
     // TODO: Test function
-    fn generate(square: Square, kind: Kind) -> MagicEntry {
-        let mask = match kind {
-            Kind::Rook => generate_rook_attack_mask(square),
-            Kind::Bishop => generate_bishop_attack_mask(square),
-            _ => todo!("Should output an error"),
-        };
-        let permutations = enumerate_blockers(mask);
-        let shift = 64 - mask.count_ones();
+    fn generate(square: Square, slider: SliderKind) -> MagicEntry {
+        let kind = slider.kind();
+        let mask = slider.mask(square);
+        let bits = mask.count_ones();
+        let shift = 64 - bits;
+        // The attacks do not depend on the magic, they are computed once
+        let permutations: Vec<(Bitboard, Bitboard)> = enumerate_blockers(mask)
+            .into_iter()
+            .map(|blockers| (blockers, compute_attack(square, blockers, kind)))
+            .collect();
 
         loop {
             // Can be replaced by loop to be sure
             // Here it is just to win time
             let magic = random::<u64>() & random::<u64>() & random::<u64>();
-            let mut attack_set = FxHashMap::default();
+            let mut attack_set: Vec<Option<Bitboard>> = vec![None; 1 << bits];
             let mut success = true;
 
-            for &blockers in &permutations {
-                // Here, we use wrapping_mul because we're not sure the number can be represented
-                // as a u16 otherwise
-                let magic_index = u16::try_from((blockers.wrapping_mul(magic)) >> shift).unwrap();
-                let attack = compute_attack(square, blockers, kind);
-
-                if let Some(existing) = attack_set.get(&magic_index) {
-                    if *existing != attack {
+            for &(blockers, attack) in &permutations {
+                // Here, we use wrapping_mul because the product overflows by design
+                let magic_index = usize::try_from(blockers.wrapping_mul(magic) >> shift).unwrap();
+                match attack_set[magic_index] {
+                    Some(existing) if existing != attack => {
                         success = false;
                         break;
                     }
-                } else {
-                    attack_set.insert(magic_index, attack);
+                    Some(_) => {}
+                    None => attack_set[magic_index] = Some(attack),
                 }
             }
 
             if success {
-                let default_attack = compute_attack(square, Bitboard(0), kind);
                 return MagicEntry {
-                    attack_set,
-                    default_attack,
+                    // Indices no blocker set maps to are never looked up
+                    attack_set: attack_set
+                        .into_iter()
+                        .map(Option::unwrap_or_default)
+                        .collect(),
                     magic,
                     shift: u8::try_from(shift).unwrap(),
+                    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+                    mask: Bitboard(0),
                 };
             }
         }
@@ -162,33 +314,284 @@ impl MagicEntry {
 /// the first time they are used)
 #[allow(clippy::missing_panics_doc, reason = "It is not suppose to panic")]
 pub fn load_magics() {
-    let a = ROOK_MAGICS[0].clone();
-    let b = BISHOP_MAGICS[0].clone();
-    assert!(!(a.default_attack == b.default_attack),);
-}
-
-pub static ROOK_MAGICS: LazyLock<[MagicEntry; 64]> =
-    LazyLock::new(|| load_or_generate("rook_magics.bin", Kind::Rook));
-pub static BISHOP_MAGICS: LazyLock<[MagicEntry; 64]> =
-    LazyLock::new(|| load_or_generate("bishop_magics.bin", Kind::Bishop));
-
-fn load_or_generate(path: &str, kind: Kind) -> [MagicEntry; 64] {
-    if Path::new(path).exists() {
-        // Decode into Vec<MagicEntry>, then convert to [MagicEntry; 64]
-        let bytes = fs::read(path).expect("Failed to read magic table file");
-        let (vec, _): (Vec<MagicEntry>, usize) =
-            bincode::serde::decode_from_slice(&bytes, bincode::config::standard())
-                .expect("Corrupted magic table file");
-        vec.try_into().expect("Decoded table must have length 64")
-    } else {
-        // Generate
-        let table: [MagicEntry; 64] =
-            from_fn(|sq| MagicEntry::generate(Square::from_usize(sq), kind));
-
-        // Encode from a slice to avoid the array bound
-        let bytes = bincode::serde::encode_to_vec(&table[..], bincode::config::standard())
-            .expect("Serialization failed");
-        fs::write(path, bytes).expect("Failed to write magic table file");
-        table
+    assert!(!ROOK_MAGICS[0].attack_set.is_empty() && !BISHOP_MAGICS[0].attack_set.is_empty());
+}
+
+/// Same as `load_magics`, reading and writing the tables under `dir`
+/// instead of the default `chessmg` directory of the user's cache
+/// directory.
+/// Returns false if the tables were already loaded from elsewhere, in
+/// which case `dir` is ignored.
+pub fn load_magics_from(dir: &Path) -> bool {
+    let configured = MAGICS_DIR.set(Some(dir.to_path_buf())).is_ok();
+    load_magics();
+    configured
+}
+
+/// Directory of the magic table files, see `load_magics_from`
+static MAGICS_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn magics_dir() -> Option<&'static Path> {
+    MAGICS_DIR.get_or_init(default_magics_dir).as_deref()
+}
+
+/// `chessmg` in the cache directory of the user, which other users can't
+/// write to. `None` if there is no such directory, the tables then being
+/// generated in memory each time.
+fn default_magics_dir() -> Option<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache.join("chessmg"))
+}
+
+pub static ROOK_MAGICS: LazyLock<[MagicEntry; 64]> = LazyLock::new(|| {
+    with_pext(
+        load_or_generate(magics_dir(), SliderKind::Rook),
+        SliderKind::Rook,
+    )
+});
+pub static BISHOP_MAGICS: LazyLock<[MagicEntry; 64]> = LazyLock::new(|| {
+    with_pext(
+        load_or_generate(magics_dir(), SliderKind::Bishop),
+        SliderKind::Bishop,
+    )
+});
+
+/// Whether `find_attack` uses the PEXT instruction: the `bmi2` feature is
+/// on and the CPU supports BMI2. Detected once, the magic lookups being
+/// used otherwise.
+pub fn pext_available() -> bool {
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    {
+        static BMI2: LazyLock<bool> =
+            LazyLock::new(|| std::arch::is_x86_feature_detected!("bmi2"));
+        *BMI2
+    }
+    #[cfg(not(all(feature = "bmi2", target_arch = "x86_64")))]
+    {
+        false
+    }
+}
+
+/// Indexes the tables of `table` with PEXT when the CPU supports it
+#[cfg_attr(not(all(feature = "bmi2", target_arch = "x86_64")), allow(unused_mut, unused_variables))]
+fn with_pext(mut table: [MagicEntry; 64], slider: SliderKind) -> [MagicEntry; 64] {
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    if pext_available() {
+        for (square, entry) in Square::iter().zip(table.iter_mut()) {
+            entry.build_pext(square, slider);
+        }
+    }
+    table
+}
+
+fn table_path(dir: &Path, slider: SliderKind) -> PathBuf {
+    match slider {
+        // Named after the format version, as older files can't be decoded
+        SliderKind::Rook => dir.join("rook_magics_v2.bin"),
+        SliderKind::Bishop => dir.join("bishop_magics_v2.bin"),
+    }
+}
+
+/// The table read from `dir` if it holds a valid one, otherwise a newly
+/// generated table, saved to `dir` for the next time
+fn load_or_generate(dir: Option<&Path>, slider: SliderKind) -> [MagicEntry; 64] {
+    if let Some(table) = dir.and_then(|dir| load_table(&table_path(dir, slider), slider)) {
+        return table;
+    }
+
+    // Missing or invalid, generated again and written over
+    let table: [MagicEntry; 64] = Square::ALL.map(|sq| MagicEntry::generate(sq, slider));
+    if let Some(dir) = dir {
+        // The table is usable anyway, a failure only means generating it
+        // again next time
+        let _ = save_table(dir, slider, &table);
+    }
+    table
+}
+
+fn save_table(dir: &Path, slider: SliderKind, table: &[MagicEntry; 64]) -> std::io::Result<()> {
+    // Encode from a slice to avoid the array bound
+    let bytes = bincode::serde::encode_to_vec(&table[..], bincode::config::standard())
+        .map_err(std::io::Error::other)?;
+    // Written to a file of our own then renamed, so that another process
+    // never reads a partially written table
+    fs::create_dir_all(dir)?;
+    let path = table_path(dir, slider);
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, &path)
+}
+
+/// The table stored at `path`, or `None` if it can't be read or decoded,
+/// or if one of its entries is not valid, see `MagicEntry::is_valid`
+fn load_table(path: &Path, slider: SliderKind) -> Option<[MagicEntry; 64]> {
+    let bytes = fs::read(path).ok()?;
+    let (vec, _): (Vec<MagicEntry>, usize) =
+        bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).ok()?;
+    let table: [MagicEntry; 64] = vec.try_into().ok()?;
+    Square::iter()
+        .zip(&table)
+        .all(|(square, entry)| entry.is_valid(square, slider))
+        .then_some(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classical_matches_magics() {
+        for _ in 0..200 {
+            // Sparse occupancies, closer to real positions
+            let occupancy = Bitboard(random::<u64>() & random::<u64>());
+            for sq in Square::iter() {
+                let square = sq as usize;
+                let own = !Bitboard(1 << square);
+
+                let blockers = occupancy & generate_rook_attack_mask(sq) & own;
+                assert!(
+                    classical_rook_attacks(sq, occupancy)
+                        == ROOK_MAGICS[square].find_attack(blockers)
+                );
+
+                let blockers = occupancy & generate_bishop_attack_mask(sq) & own;
+                assert!(
+                    classical_bishop_attacks(sq, occupancy)
+                        == BISHOP_MAGICS[square].find_attack(blockers)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_magics_match_computed_attacks() {
+        for square in [Square::A1, Square::D4, Square::H8, Square::B7, Square::G2] {
+            let rook_mask = generate_rook_attack_mask(square);
+            let bishop_mask = generate_bishop_attack_mask(square);
+            for _ in 0..500 {
+                let blockers = Bitboard(random::<u64>()) & rook_mask;
+                assert!(
+                    ROOK_MAGICS[square as usize].find_attack(blockers)
+                        == compute_attack(square, blockers, Kind::Rook)
+                );
+                let blockers = Bitboard(random::<u64>()) & bishop_mask;
+                assert!(
+                    BISHOP_MAGICS[square as usize].find_attack(blockers)
+                        == compute_attack(square, blockers, Kind::Bishop)
+                );
+            }
+            let entry = &ROOK_MAGICS[square as usize];
+            assert_eq!(entry.attack_set.len(), 1 << (64 - entry.shift));
+        }
+    }
+
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    #[test]
+    fn test_pext_matches_computed_attacks() {
+        if !pext_available() {
+            return;
+        }
+        for sq in Square::iter() {
+            let square = sq as usize;
+            assert!(ROOK_MAGICS[square].mask == generate_rook_attack_mask(sq));
+            assert!(BISHOP_MAGICS[square].mask == generate_bishop_attack_mask(sq));
+            for _ in 0..100 {
+                let blockers = Bitboard(random::<u64>()) & generate_rook_attack_mask(sq);
+                assert!(
+                    ROOK_MAGICS[square].find_attack(blockers)
+                        == compute_attack(sq, blockers, Kind::Rook)
+                );
+
+                let blockers = Bitboard(random::<u64>()) & generate_bishop_attack_mask(sq);
+                assert!(
+                    BISHOP_MAGICS[square].find_attack(blockers)
+                        == compute_attack(sq, blockers, Kind::Bishop)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_or_generate_in_dir() {
+        let dir = std::env::temp_dir().join(format!("chessmg-magics-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let generated = load_or_generate(Some(&dir), SliderKind::Bishop);
+        assert!(dir.join("bishop_magics_v2.bin").exists());
+        assert!(!dir.join("rook_magics_v2.bin").exists());
+
+        // Loaded back from the file this time
+        let loaded = load_or_generate(Some(&dir), SliderKind::Bishop);
+        for (a, b) in generated.iter().zip(&loaded) {
+            assert_eq!(a.magic, b.magic);
+        }
+        let occupancy = Bitboard(0x0042_0000_1800_2400);
+        let blockers = occupancy & generate_bishop_attack_mask(Square::D4);
+        assert!(
+            loaded[Square::D4 as usize].find_magic_attack(blockers)
+                == classical_bishop_attacks(Square::D4, occupancy)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_without_dir() {
+        let table = load_or_generate(None, SliderKind::Bishop);
+        assert!(Square::iter()
+            .zip(&table)
+            .all(|(square, entry)| entry.is_valid(square, SliderKind::Bishop)));
+    }
+
+    #[test]
+    fn test_invalid_table_is_regenerated() {
+        let dir = std::env::temp_dir().join(format!("chessmg-invalid-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bishop_magics_v2.bin");
+        let occupancy = Bitboard(0x0042_0000_1800_2400);
+        let blockers = occupancy & generate_bishop_attack_mask(Square::D4);
+
+        // Not a table at all
+        fs::write(&path, b"garbage").unwrap();
+        let table = load_or_generate(Some(&dir), SliderKind::Bishop);
+        assert!(
+            table[Square::D4 as usize].find_magic_attack(blockers)
+                == classical_bishop_attacks(Square::D4, occupancy)
+        );
+
+        // Truncated attack sets, which would be indexed out of bounds
+        let mut truncated = table.clone();
+        for entry in &mut truncated {
+            entry.attack_set.truncate(1);
+        }
+        let bytes =
+            bincode::serde::encode_to_vec(&truncated[..], bincode::config::standard()).unwrap();
+        fs::write(&path, bytes).unwrap();
+        assert!(load_table(&path, SliderKind::Bishop).is_none());
+        let table = load_or_generate(Some(&dir), SliderKind::Bishop);
+        assert!(
+            table[Square::D4 as usize].find_magic_attack(blockers)
+                == classical_bishop_attacks(Square::D4, occupancy)
+        );
+        assert!(load_table(&path, SliderKind::Bishop).is_some());
+
+        // Well formed, but with wrong attacks planted in it
+        let mut planted = table.clone();
+        for entry in &mut planted {
+            for attack in &mut entry.attack_set {
+                *attack = Bitboard(u64::MAX);
+            }
+        }
+        let bytes =
+            bincode::serde::encode_to_vec(&planted[..], bincode::config::standard()).unwrap();
+        fs::write(&path, bytes).unwrap();
+        assert!(load_table(&path, SliderKind::Bishop).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -4,10 +4,14 @@ use crate::magic::{
     generate_bishop_attack_mask, generate_rook_attack_mask, BISHOP_MAGICS, ROOK_MAGICS,
 };
 use crate::utils::{
-    square_mask, Casteling, Color, Kind, Square, CLEAR_FILE, CLEAR_RANK, MASK_RANK,
+    square_mask, Casteling, CastleSide, Color, Kind, Square, CLEAR_FILE, EAST_RAY, MASK_RANK,
+    NORTH_EAST_RAY, NORTH_RAY, NORTH_WEST_RAY, SOUTH_EAST_RAY, SOUTH_RAY, SOUTH_WEST_RAY, WEST_RAY,
 };
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::LazyLock;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Move {
     pub piece_kind: Kind,
     pub piece_color: Color,
@@ -20,12 +24,36 @@ pub struct Move {
     pub captured_piece: Option<Kind>,
 }
 
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_uci())
+    }
+}
+
+/// Classification of a move, derived from its flags
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoveType {
+    Quiet,
+    Capture,
+    DoublePush,
+    EnPassant,
+    Castle,
+    Promotion,
+    CapturePromotion,
+}
+
+/// The state needed by `Board::unmake_move` to take a move back
 #[derive(Clone)]
-pub struct Undo {
-    pub captured_piece: Option<(Kind, Color, Square)>,
+pub struct UndoInfo {
+    /// The captured piece and the square it was on, which is not `to`
+    /// for an en passant capture
+    pub captured: Option<(Kind, Square)>,
     pub castling_rights: Casteling,
     pub en_passant: Option<Square>,
     pub to_move: Color,
+    pub hash: u64,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
 }
 
 impl Move {
@@ -49,14 +77,9 @@ impl Move {
         print!("");
     }
 
-    pub fn to_string(&self) -> String {
-        fn square_to_str(square: Square) -> String {
-            let (file, rank) = square.to_coords(); // (0..7, 0..7)
-            let file_char = (b'a' + file) as char;
-            let rank_char = (b'1' + rank) as char;
-            format!("{file_char}{rank_char}")
-        }
-
+    /// Formats the move in UCI long algebraic notation (`e2e4`, `e7e8q`),
+    /// castling being written as the king's move (`e1g1`).
+    pub fn to_uci(&self) -> String {
         fn kind_to_uci_char(kind: Kind) -> char {
             match kind {
                 Kind::Queen => 'q',
@@ -67,9 +90,7 @@ impl Move {
             }
         }
 
-        let mut s = String::new();
-        s.push_str(&square_to_str(self.from));
-        s.push_str(&square_to_str(self.to));
+        let mut s = format!("{}{}", self.from, self.to);
 
         if let Some(prom) = self.promoting_piece {
             s.push(kind_to_uci_char(prom));
@@ -77,843 +98,733 @@ impl Move {
 
         s
     }
-}
-
-pub struct MoveGen<'a> {
-    pub board: &'a Board,
-    pub pseudo_move_list: Vec<Move>,
-    pub legal_move_list: Vec<Move>,
-}
-
-impl<'a> MoveGen<'a> {
-    // The only pub function should be the following
-    // For debuggind purposes, all functions are now pub
 
-    pub fn new(board: &'a Board) -> Self {
-        Self {
-            board,
-            pseudo_move_list: Vec::with_capacity(500),
-            legal_move_list: Vec::with_capacity(500),
-        }
-    }
-
-    pub fn get_legal_moves(&self) -> &Vec<Move> {
-        &self.legal_move_list
-    }
-
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_legal_moves(&mut self) {
-        self.gen_pseudo_moves();
-        let old_items = std::mem::take(&mut self.pseudo_move_list);
-        for m in old_items {
-            let eat_king = m.captured_piece.is_some_and(|p| p == Kind::King);
-            if m.casteling {
-                let can_castle: bool = !match m.to {
-                    Square::G1 => {
-                        self.is_square_under_black_attack(Square::E1)
-                            || self.is_square_under_black_attack(Square::F1)
-                            || self.is_square_under_black_attack(Square::G1)
-                    }
-                    Square::C1 => {
-                        self.is_square_under_black_attack(Square::E1)
-                            || self.is_square_under_black_attack(Square::D1)
-                            || self.is_square_under_black_attack(Square::C1)
-                    }
-                    Square::G8 => {
-                        self.is_square_under_white_attack(Square::E8)
-                            || self.is_square_under_white_attack(Square::F8)
-                            || self.is_square_under_white_attack(Square::G8)
-                    }
-                    Square::C8 => {
-                        self.is_square_under_white_attack(Square::E8)
-                            || self.is_square_under_white_attack(Square::D8)
-                            || self.is_square_under_white_attack(Square::C8)
-                    }
-                    _ => panic!(),
-                };
-                if !can_castle {
-                    continue;
-                }
-            }
-            let mut tmp_board: Board = self.board.clone();
-            tmp_board.do_move(&m);
-            // Skip adding this move if it results in moving into check
-            if !tmp_board.is_in_check(self.board.to_move) && !eat_king {
-                self.legal_move_list.push(m);
+    pub fn kind_of(&self) -> MoveType {
+        if self.casteling {
+            MoveType::Castle
+        } else if self.en_passant {
+            MoveType::EnPassant
+        } else if self.promoting_piece.is_some() {
+            if self.captured_piece.is_some() {
+                MoveType::CapturePromotion
+            } else {
+                MoveType::Promotion
             }
+        } else if self.captured_piece.is_some() {
+            MoveType::Capture
+        } else if self.double_push {
+            MoveType::DoublePush
+        } else {
+            MoveType::Quiet
         }
     }
 
-    pub fn get_pseudo_moves(&self) -> &Vec<Move> {
-        &self.pseudo_move_list
-    }
-
-    pub fn gen_pseudo_moves(&mut self) {
-        match self.board.to_move {
-            Color::White => self.gen_white_moves(),
-            Color::Black => self.gen_black_moves(),
+    /// Most Valuable Victim / Least Valuable Attacker score, for trying the
+    /// most promising captures first: `victim_value * 10 - attacker_value`.
+    /// The value of a promoted piece counts as a victim, so promotions score
+    /// high, and the king is the least attractive attacker.
+    pub fn mvv_lva_score(&self) -> i32 {
+        let victim = self.captured_piece.map_or(0, Kind::value);
+        let promotion = self.promoting_piece.map_or(0, Kind::value);
+        let attacker = match self.piece_kind {
+            Kind::King => 10 * Kind::Queen.value(),
+            kind => kind.value(),
+        };
+        (victim + promotion) * 10 - attacker
+    }
+
+    /// Packs the move in 16 bits: `from` in bits 0-5, `to` in bits 6-11 and
+    /// a flag in bits 12-15: 0 quiet, 1 double push, 2 and 3 king and queen
+    /// side castling, 4 capture, 5 en passant, then 8-11 the promotions to
+    /// a knight, bishop, rook and queen, plus 4 if they capture.
+    /// See `Board::move_from_compact` for the way back.
+    pub fn to_compact(&self) -> u16 {
+        let promotion = |kind: Option<Kind>| match kind {
+            Some(Kind::Bishop) => 9,
+            Some(Kind::Rook) => 10,
+            Some(Kind::Queen) => 11,
+            _ => 8,
+        };
+        let flag = match self.kind_of() {
+            MoveType::Quiet => 0,
+            MoveType::DoublePush => 1,
+            MoveType::Castle if self.to.file() == 6 => 2,
+            MoveType::Castle => 3,
+            MoveType::Capture => 4,
+            MoveType::EnPassant => 5,
+            MoveType::Promotion => promotion(self.promoting_piece),
+            MoveType::CapturePromotion => promotion(self.promoting_piece) + 4,
+        };
+        self.from as u16 | (self.to as u16) << 6 | flag << 12
+    }
+
+    /// Square of the captured piece: `to`, except for an en passant capture
+    /// where the captured pawn sits behind it.
+    pub fn captured_square(&self) -> Square {
+        if !self.en_passant {
+            return self.to;
         }
-    }
-
-    pub fn gen_white_moves(&mut self) {
-        self.gen_white_pawns_moves();
-        self.gen_white_knight_moves();
-        self.gen_white_rook_moves();
-        self.gen_white_bishop_moves();
-        self.gen_white_queen_moves();
-        self.gen_white_king_moves();
-    }
-
-    pub fn gen_black_moves(&mut self) {
-        self.gen_black_pawns_moves();
-        self.gen_black_knight_moves();
-        self.gen_black_rook_moves();
-        self.gen_black_bishop_moves();
-        self.gen_black_queen_moves();
-        self.gen_black_king_moves();
-    }
-
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_white_pawn_single_move(&mut self) {
-        let mut moved_pawns = self.board.white_pawn.bitboard << 8;
-        let free_squares = !self.board.all_pieces();
-        moved_pawns = moved_pawns & free_squares;
-
-        let mut promotions: Bitboard = moved_pawns & MASK_RANK[7];
-        moved_pawns = moved_pawns & !MASK_RANK[7];
-
-        // Generate single non promotion moves
-        while moved_pawns != 0 {
-            // Safe to unwrap thanks to previous check
-            let to = moved_pawns.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-        }
-
-        // Generate promotions
-        while promotions != 0 {
-            // Safe to unwrap thanks to previous check
-            let to = promotions.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
+        match self.piece_color {
+            Color::White => Square::from_usize(self.to as usize - 8),
+            Color::Black => Square::from_usize(self.to as usize + 8),
         }
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_white_pawn_double_move(&mut self) {
-        let free_squares: Bitboard = !self.board.all_pieces();
-        let single_pushes: Bitboard = (self.board.white_pawn.bitboard << 8) & free_squares;
-        let mut double_pushes: Bitboard = (single_pushes << 8) & free_squares & MASK_RANK[3];
-
-        while double_pushes != 0 {
-            let to = double_pushes.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 16),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: true,
-                en_passant: false,
-                captured_piece: None,
-            });
-        }
+    /// Formats the move in Standard Algebraic Notation (e.g. `Nbd2`, `exd5`,
+    /// `e8=Q+`, `O-O`), given the position it is played from.
+    pub fn to_san(&self, board: &Board) -> String {
+        let mut mg = MoveGen::new(board);
+        mg.gen_legal_moves();
+        self.to_san_with(board, mg.get_legal_moves())
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_white_pawn_left_attack(&mut self) {
-        let mut left_regular_attacks =
-            (self.board.white_pawn.bitboard << 7) & self.board.all_black_pieces() & CLEAR_FILE[7];
-        let mut left_attack_promotions = left_regular_attacks & MASK_RANK[7];
-        left_regular_attacks = left_regular_attacks & CLEAR_RANK[7];
+    /// Same as `to_san`, with the legal moves of `board` already generated,
+    /// to format many moves of the same position without generating them
+    /// again for each one.
+    pub fn to_san_with(&self, board: &Board, legal_moves: &[Move]) -> String {
+        let mut s = String::new();
 
-        let mut left_en_passant =
-            (self.board.white_pawn.bitboard << 7) & self.board.get_en_passant() & CLEAR_FILE[7];
+        if self.casteling {
+            // The king always lands on the g or c file
+            if self.to.file() == 6 {
+                s.push_str("O-O");
+            } else {
+                s.push_str("O-O-O");
+            }
+        } else {
+            let (from_file, from_rank) = self.from.to_coords();
 
-        while left_regular_attacks != 0 {
-            let to = left_regular_attacks.pop_lsb().unwrap();
+            if self.piece_kind == Kind::Pawn {
+                if self.captured_piece.is_some() {
+                    s.push(self.from.file_char());
+                }
+            } else {
+                s.push(self.piece_kind.to_fen_char(Color::White));
+
+                // Other pieces of the same kind that could land on the same square
+                let ambiguous: Vec<&Move> = legal_moves
+                    .iter()
+                    .filter(|m| {
+                        m.piece_kind == self.piece_kind && m.to == self.to && m.from != self.from
+                    })
+                    .collect();
+
+                if !ambiguous.is_empty() {
+                    let same_file = ambiguous.iter().any(|m| m.from.file() == from_file);
+                    let same_rank = ambiguous.iter().any(|m| m.from.rank() == from_rank);
+                    if !same_file {
+                        s.push(self.from.file_char());
+                    } else if !same_rank {
+                        s.push(self.from.rank_char());
+                    } else {
+                        s.push_str(&self.from.to_string());
+                    }
+                }
+            }
 
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
+            if self.captured_piece.is_some() {
+                s.push('x');
+            }
+            s.push_str(self.to.square_to_str());
 
-            let m = Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            };
-            self.pseudo_move_list.push(m);
+            if let Some(prom) = self.promoting_piece {
+                s.push('=');
+                s.push(prom.to_fen_char(Color::White));
+            }
         }
 
-        while left_attack_promotions != 0 {
-            let to = left_attack_promotions.pop_lsb().unwrap();
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
+        // Check and checkmate suffixes
+        let mut after = board.clone();
+        after.do_move(self);
+        if after.is_in_check(after.to_move) {
+            let mut mg = MoveGen::new(&after);
+            mg.gen_legal_moves();
+            if mg.get_legal_moves().is_empty() {
+                s.push('#');
+            } else {
+                s.push('+');
+            }
         }
 
-        if left_en_passant != 0 {
-            let to = left_en_passant.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                captured_piece: Some(Kind::Pawn),
-                en_passant: true,
-            });
-        }
+        s
     }
+}
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_white_pawn_right_attack(&mut self) {
-        let mut right_regular_attacks =
-            (self.board.white_pawn.bitboard << 9) & self.board.all_black_pieces() & CLEAR_FILE[0];
-        let mut right_attack_promotions = right_regular_attacks & MASK_RANK[7];
-        right_regular_attacks = right_regular_attacks & CLEAR_RANK[7];
-
-        let mut right_en_passant =
-            (self.board.white_pawn.bitboard << 9) & self.board.get_en_passant() & CLEAR_FILE[0];
-
-        while right_regular_attacks != 0 {
-            let to = right_regular_attacks.pop_lsb().unwrap();
+pub struct MoveGen<'a> {
+    pub board: &'a Board,
+    pub pseudo_move_list: Vec<Move>,
+    pub legal_move_list: Vec<Move>,
+    pub capture_move_list: Vec<Move>,
+    // Only generate captures and promotions, see `gen_capture_moves`
+    noisy_only: bool,
+    // Allowed destinations, see `gen_pseudo_to_mask`
+    target_mask: Bitboard,
+    // Set by `count_legal_moves`: the copy of the board the generated moves
+    // are tried on, to count the legal ones instead of pushing them
+    counting: Option<RefCell<Board>>,
+    legal_count: usize,
+}
 
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
+impl<'a> MoveGen<'a> {
+    // The only pub function should be the following
+    // For debuggind purposes, all functions are now pub
 
-            let m = Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            };
-            self.pseudo_move_list.push(m);
+    pub fn new(board: &'a Board) -> Self {
+        Self {
+            board,
+            pseudo_move_list: Vec::with_capacity(500),
+            legal_move_list: Vec::with_capacity(500),
+            capture_move_list: Vec::new(),
+            noisy_only: false,
+            target_mask: Bitboard(u64::MAX),
+            counting: None,
+            legal_count: 0,
         }
+    }
 
-        while right_attack_promotions != 0 {
-            let to = right_attack_promotions.pop_lsb().unwrap();
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
+    /// A generator whose move lists start empty, without reserving any
+    /// capacity, for `Board::count_legal_moves` which never fills them.
+    pub(crate) fn without_capacity(board: &'a Board) -> Self {
+        Self {
+            board,
+            pseudo_move_list: Vec::new(),
+            legal_move_list: Vec::new(),
+            capture_move_list: Vec::new(),
+            noisy_only: false,
+            target_mask: Bitboard(u64::MAX),
+            counting: None,
+            legal_count: 0,
         }
+    }
 
-        if right_en_passant != 0 {
-            let to = right_en_passant.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::White,
-                from: Square::from_usize(to - 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                captured_piece: Some(Kind::Pawn),
-                en_passant: true,
-            });
-        }
+    /// Empties the move lists, keeping their capacity for the next
+    /// generation.
+    pub fn clear(&mut self) {
+        self.pseudo_move_list.clear();
+        self.legal_move_list.clear();
+        self.capture_move_list.clear();
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_black_pawn_single_move(&mut self) {
-        let mut moved_pawns = self.board.black_pawn.bitboard >> 8;
-        let free_squares = !self.board.all_pieces();
-        moved_pawns = moved_pawns & free_squares;
+    /// Points the generator to another position and clears the move lists,
+    /// so that a single `MoveGen` (and its allocations) can be reused for
+    /// many positions, e.g. one per search depth: create it once with `new`,
+    /// then call `set_board` before each `gen_legal_moves`.
+    pub fn set_board(&mut self, board: &'a Board) {
+        self.board = board;
+        self.clear();
+    }
 
-        let mut promotions: Bitboard = moved_pawns & MASK_RANK[0];
-        moved_pawns = moved_pawns & CLEAR_RANK[0];
+    pub fn get_legal_moves(&self) -> &Vec<Move> {
+        &self.legal_move_list
+    }
 
-        // Generate single non promotion moves
-        while moved_pawns != 0 {
-            // Safe to unwrap thanks to previous check
-            let to = moved_pawns.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-        }
+    /// The squares the piece on `from` can legally move to, e.g. to show
+    /// move hints in a UI. Meant to be called after `gen_legal_moves`.
+    pub fn legal_destinations(&self, from: Square) -> Bitboard {
+        self.legal_move_list
+            .iter()
+            .filter(|m| m.from == from)
+            .fold(Bitboard(0), |acc, m| acc | square_mask(m.to))
+    }
 
-        // Generate promotions
-        while promotions != 0 {
-            // Safe to unwrap thanks to previous check
-            let to = promotions.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 8),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece: None,
-            });
+    pub fn gen_legal_moves(&mut self) {
+        self.gen_candidates();
+        // Moved out while checking the moves, and put back empty so that
+        // its capacity is kept for the next generation
+        let mut candidates = std::mem::take(&mut self.pseudo_move_list);
+        let mut scratch = self.board.clone();
+        for m in candidates.drain(..) {
+            if self.is_legal(&m, &mut scratch) {
+                self.legal_move_list.push(m);
+            }
+        }
+        self.pseudo_move_list = candidates;
+    }
+
+    /// Number of legal moves. No move list is filled: each generated move
+    /// is checked for legality and counted on the spot.
+    pub fn count_legal_moves(&mut self) -> usize {
+        self.counting = Some(RefCell::new(self.board.clone()));
+        self.legal_count = 0;
+        self.gen_candidates();
+        self.counting = None;
+        self.legal_count
+    }
+
+    /// Generates the pseudo legal moves to check for legality: all of
+    /// them, or only the evasions when in check.
+    fn gen_candidates(&mut self) {
+        let checkers = self.board.checkers();
+        if checkers == 0 {
+            self.gen_pseudo_moves();
+        } else {
+            self.gen_evasions(checkers);
         }
     }
-
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_black_pawn_double_move(&mut self) {
-        let free_squares: Bitboard = !self.board.all_pieces();
-        let single_pushes: Bitboard = (self.board.black_pawn.bitboard >> 8) & free_squares;
-        let mut double_pushes: Bitboard = (single_pushes >> 8) & free_squares & MASK_RANK[4];
-
-        while double_pushes != 0 {
-            let to = double_pushes.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 16),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: true,
-                en_passant: false,
-                captured_piece: None,
-            });
+
+    /// Adds a generated move to `pseudo_move_list`, or only counts it if it
+    /// is legal, see `count_legal_moves`.
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if self.counting.is_some() {
+            self.count_if_legal(&m);
+        } else {
+            self.pseudo_move_list.push(m);
         }
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_black_pawn_left_attack(&mut self) {
-        let mut left_regular_attacks =
-            (self.board.black_pawn.bitboard >> 7) & self.board.all_white_pieces() & CLEAR_FILE[0];
-        let mut left_attack_promotions = left_regular_attacks & MASK_RANK[0];
-        left_regular_attacks = left_regular_attacks & CLEAR_RANK[0];
+    // Kept out of line, so that `push` stays small in every generator
+    #[inline(never)]
+    fn count_if_legal(&mut self, m: &Move) {
+        let legal = self
+            .counting
+            .as_ref()
+            .is_some_and(|scratch| self.is_legal(m, &mut scratch.borrow_mut()));
+        self.legal_count += usize::from(legal);
+    }
 
-        let mut left_en_passant =
-            (self.board.black_pawn.bitboard >> 7) & self.board.get_en_passant() & CLEAR_FILE[0];
+    /// Generates the pseudo legal moves that may answer the check given by
+    /// `checkers`: king moves, and against a single checker, its capture
+    /// and the moves onto the line between it and the king. In double
+    /// check, only the king is moved.
+    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
+    fn gen_evasions(&mut self, checkers: Bitboard) {
+        let color = self.board.to_move;
+        if checkers.count_ones() == 1 {
+            let checker = Square::from_usize(checkers.clone().pop_lsb().unwrap());
+            let king = self.board.king_square(color);
+            // En passant takes a checking pawn without landing on it, which
+            // the target mask allows for
+            self.target_mask = checkers | squares_between(king, checker);
+            self.gen_pawn_moves(color);
+            match color {
+                Color::White => {
+                    self.gen_white_knight_moves();
+                    self.gen_white_rook_moves();
+                    self.gen_white_bishop_moves();
+                    self.gen_white_queen_moves();
+                }
+                Color::Black => {
+                    self.gen_black_knight_moves();
+                    self.gen_black_rook_moves();
+                    self.gen_black_bishop_moves();
+                    self.gen_black_queen_moves();
+                }
+            }
+            self.target_mask = Bitboard(u64::MAX);
+        }
+        self.gen_king_steps(color);
+    }
 
-        while left_regular_attacks != 0 {
-            let to = left_regular_attacks.pop_lsb().unwrap();
+    pub fn get_capture_moves(&self) -> &Vec<Move> {
+        &self.capture_move_list
+    }
 
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
+    /// The moves generated by `gen_capture_moves`, best MVV-LVA score first.
+    pub fn ordered_captures(&self) -> Vec<&Move> {
+        let mut captures: Vec<&Move> = self.capture_move_list.iter().collect();
+        captures.sort_by_key(|m| std::cmp::Reverse(m.mvv_lva_score()));
+        captures
+    }
 
-            let m = Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            };
-            self.pseudo_move_list.push(m);
+    /// Generates the legal captures and promotions, e.g. for a quiescence
+    /// search. Quiet moves are not generated at all: destinations are
+    /// restricted to enemy pieces, and pawn pushes to the last rank.
+    pub fn gen_capture_moves(&mut self) {
+        let noisy = self.gen_noisy();
+        let mut scratch = self.board.clone();
+        for m in noisy {
+            if self.is_legal(&m, &mut scratch) {
+                self.capture_move_list.push(m);
+            }
         }
+    }
 
-        while left_attack_promotions != 0 {
-            let to = left_attack_promotions.pop_lsb().unwrap();
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
+    /// The pseudo legal captures and promotions, kept apart from
+    /// `pseudo_move_list`, which is left untouched.
+    fn gen_noisy(&mut self) -> Vec<Move> {
+        let start = self.pseudo_move_list.len();
+        self.noisy_only = true;
+        self.gen_pseudo_moves();
+        self.noisy_only = false;
+        self.pseudo_move_list.split_off(start)
+    }
+
+    /// The pseudo legal moves that are neither captures nor promotions,
+    /// kept apart from `pseudo_move_list`, which is left untouched. The
+    /// destinations are restricted to squares without an enemy piece.
+    fn gen_quiets(&mut self) -> Vec<Move> {
+        let enemies = match self.board.to_move {
+            Color::White => self.board.all_black_pieces(),
+            Color::Black => self.board.all_white_pieces(),
+        };
+        let start = self.pseudo_move_list.len();
+        self.gen_pseudo_to_mask(!enemies);
+        let mut quiets = self.pseudo_move_list.split_off(start);
+        quiets.retain(|m| m.promoting_piece.is_none() && !m.en_passant);
+        quiets
+    }
+
+    /// Destinations allowed for quiet moves: none when only generating
+    /// captures and promotions.
+    fn quiet_mask(&self) -> Bitboard {
+        if self.noisy_only {
+            Bitboard(0)
+        } else {
+            self.target_mask
         }
+    }
 
-        if left_en_passant != 0 {
-            let to = left_en_passant.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 7),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                captured_piece: Some(Kind::Pawn),
-                en_passant: true,
-            });
+    /// Whether the side to move has a legal capture or promotion.
+    /// Stops at the first one found, which is cheaper than `gen_legal_moves`
+    /// when only the answer matters (e.g. in a quiescence search). Only
+    /// the captures and promotions are generated, one kind of piece at a
+    /// time, so the pieces after the first legal capture are never
+    /// generated. None of the move lists is filled.
+    pub fn has_legal_capture(&mut self) -> bool {
+        let color = self.board.to_move;
+        let start = self.pseudo_move_list.len();
+        let mut scratch = self.board.clone();
+        let mut found = false;
+        self.noisy_only = true;
+        for kind in [
+            Kind::Pawn,
+            Kind::Knight,
+            Kind::Bishop,
+            Kind::Rook,
+            Kind::Queen,
+            Kind::King,
+        ] {
+            self.gen_piece_moves(color, kind);
+            let noisy = self.pseudo_move_list.split_off(start);
+            if noisy.iter().any(|m| self.is_legal(m, &mut scratch)) {
+                found = true;
+                break;
+            }
+        }
+        self.noisy_only = false;
+        found
+    }
+
+    /// Generates the pseudo legal moves of the pieces of `kind` and `color`.
+    fn gen_piece_moves(&mut self, color: Color, kind: Kind) {
+        match (kind, color) {
+            (Kind::Pawn, color) => self.gen_pawn_moves(color),
+            (Kind::Knight, Color::White) => self.gen_white_knight_moves(),
+            (Kind::Knight, Color::Black) => self.gen_black_knight_moves(),
+            (Kind::Bishop, Color::White) => self.gen_white_bishop_moves(),
+            (Kind::Bishop, Color::Black) => self.gen_black_bishop_moves(),
+            (Kind::Rook, Color::White) => self.gen_white_rook_moves(),
+            (Kind::Rook, Color::Black) => self.gen_black_rook_moves(),
+            (Kind::Queen, Color::White) => self.gen_white_queen_moves(),
+            (Kind::Queen, Color::Black) => self.gen_black_queen_moves(),
+            (Kind::King, Color::White) => self.gen_white_king_moves(),
+            (Kind::King, Color::Black) => self.gen_black_king_moves(),
         }
     }
 
+    /// Whether a pseudo legal move is legal: it must not castle out of,
+    /// through or into check, capture the king, or leave the king in check.
+    /// `scratch` must be a copy of the board, the move is tried on it
+    /// and taken back.
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_black_pawn_right_attack(&mut self) {
-        let mut left_regular_attacks =
-            (self.board.black_pawn.bitboard >> 9) & self.board.all_white_pieces() & CLEAR_FILE[7];
-        let mut left_attack_promotions = left_regular_attacks & MASK_RANK[0];
-        left_regular_attacks = left_regular_attacks & CLEAR_RANK[0];
-
-        let mut left_en_passant =
-            (self.board.black_pawn.bitboard >> 9) & self.board.get_en_passant() & CLEAR_FILE[7];
-
-        while left_regular_attacks != 0 {
-            let to = left_regular_attacks.pop_lsb().unwrap();
-
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-
-            let m = Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            };
-            self.pseudo_move_list.push(m);
+    fn is_legal(&self, m: &Move, scratch: &mut Board) -> bool {
+        let eat_king = m.captured_piece.is_some_and(|p| p == Kind::King);
+        // The king can't castle out of, through or into check
+        if m.casteling
+            && rank_span(m.from as usize, m.to as usize)
+                .iter_squares()
+                .any(|sq| self.is_square_under_attack(sq, self.board.to_move.opposite()))
+        {
+            return false;
         }
-
-        while left_attack_promotions != 0 {
-            let to = left_attack_promotions.pop_lsb().unwrap();
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Queen),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Rook),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Bishop),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: Some(Kind::Knight),
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            });
+        // A king move is legal if its destination is safe, which can be
+        // checked without playing it
+        if m.piece_kind == Kind::King && !m.casteling {
+            return !eat_king && !self.is_king_escape_attacked(m.to, self.board.to_move.opposite());
         }
-
-        if left_en_passant != 0 {
-            let to = left_en_passant.pop_lsb().unwrap();
-            self.pseudo_move_list.push(Move {
-                piece_kind: Kind::Pawn,
-                piece_color: Color::Black,
-                from: Square::from_usize(to + 9),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                captured_piece: Some(Kind::Pawn),
-                en_passant: true,
-            });
+        let undo = scratch.make_move(m);
+        // The move is illegal if it results in moving into check
+        let legal = !scratch.is_in_check(self.board.to_move) && !eat_king;
+        scratch.unmake_move(m, undo);
+        legal
+    }
+
+    /// Checks a few invariants every generated move must respect, to catch
+    /// generator bugs early. This is a no-op in release builds.
+    ///
+    /// # Panics
+    /// In debug builds, panics if a pseudo or legal move:
+    /// - goes from a square to itself,
+    /// - captures a friendly piece,
+    /// - promotes somewhere else than on the last rank,
+    /// - castles from somewhere else than the king's home square,
+    /// - has a `captured_piece` that is not the piece actually on `to`
+    ///   (or, for en passant, does not land on the en passant square).
+    pub fn debug_assert_consistency(&self) {
+        for m in self.pseudo_move_list.iter().chain(&self.legal_move_list) {
+            let (friendly, last_rank, home_rank) = match m.piece_color {
+                Color::White => (self.board.all_white_pieces(), MASK_RANK[7], MASK_RANK[0]),
+                Color::Black => (self.board.all_black_pieces(), MASK_RANK[0], MASK_RANK[7]),
+            };
+            let to = square_mask(m.to);
+
+            // In Chess960, the king may already be on its castling square,
+            // or land on the home square of its own rook
+            debug_assert!(m.from != m.to || m.casteling, "{}: null move", m.to_uci());
+            debug_assert!(
+                friendly & to == 0 || m.casteling,
+                "{}: captures a friendly piece",
+                m.to_uci()
+            );
+            debug_assert!(
+                m.promoting_piece.is_none() || (m.piece_kind == Kind::Pawn && last_rank & to != 0),
+                "{}: promotion outside of the last rank",
+                m.to_uci()
+            );
+            debug_assert!(
+                !m.casteling
+                    || (m.piece_kind == Kind::King && home_rank & square_mask(m.from) != 0),
+                "{}: castling from outside the king's home rank",
+                m.to_uci()
+            );
+            if m.en_passant {
+                debug_assert!(
+                    self.board.en_passant == Some(m.to) && m.captured_piece == Some(Kind::Pawn),
+                    "{}: inconsistent en passant capture",
+                    m.to_uci()
+                );
+            } else if m.casteling {
+                debug_assert!(
+                    m.captured_piece.is_none(),
+                    "{}: castling captures a piece",
+                    m.to_uci()
+                );
+            } else {
+                debug_assert!(
+                    m.captured_piece == self.board.get_piece_kind(m.to),
+                    "{}: captured piece does not match the occupant",
+                    m.to_uci()
+                );
+            }
         }
     }
 
-    pub fn gen_white_pawns_moves(&mut self) {
-        self.gen_white_pawn_single_move();
-        self.gen_white_pawn_double_move();
-        self.gen_white_pawn_left_attack();
-        self.gen_white_pawn_right_attack();
+    pub fn get_pseudo_moves(&self) -> &Vec<Move> {
+        &self.pseudo_move_list
     }
 
-    pub fn gen_black_pawns_moves(&mut self) {
-        self.gen_black_pawn_single_move();
-        self.gen_black_pawn_double_move();
-        self.gen_black_pawn_left_attack();
-        self.gen_black_pawn_right_attack();
+    pub fn gen_pseudo_moves(&mut self) {
+        self.gen_moves_for(self.board.to_move);
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_white_king_moves(&mut self) {
-        // Square nums
-        //     . . . . .
-        //     . 1 2 3 .
-        //     . 8 K 4 .
-        //     . 7 6 5 .
-        //     . . . . .
-
-        let king_bitboard = self.board.white_king.bitboard;
+    /// Generates the pseudo legal moves of `color`, whether or not it is
+    /// its turn. En passant captures are only generated for the side to
+    /// move, the only one the en passant square is meant for.
+    pub fn gen_moves_for(&mut self, color: Color) {
+        match color {
+            Color::White => self.gen_white_moves(),
+            Color::Black => self.gen_black_moves(),
+        }
+    }
 
-        // We need to clip the h and a file of the king to calculate the sport 1, 3, 4, 5, 7 and 8
-        // to avoid king teleportation to the other side of the board
-        let king_clip_file_h = king_bitboard & CLEAR_FILE[7];
-        let king_clip_file_a = king_bitboard & CLEAR_FILE[0];
+    /// Generates the pseudo legal moves whose destination is in `targets`,
+    /// e.g. the squares that resolve a check. The other destinations are
+    /// not generated at all. An en passant capture is also generated when
+    /// the pawn it takes is in `targets`.
+    pub fn gen_pseudo_to_mask(&mut self, targets: Bitboard) {
+        self.target_mask = targets;
+        self.gen_pseudo_moves();
+        self.target_mask = Bitboard(u64::MAX);
+    }
+
+    /// Generates the pseudo legal captures, en passant included. Like
+    /// `gen_capture_moves`, quiet moves are not generated at all, and the
+    /// destinations are restricted to the enemy pieces and the en passant
+    /// square, which leaves out the promotions without a capture.
+    pub fn gen_captures(&mut self) {
+        let enemies = match self.board.to_move {
+            Color::White => self.board.all_black_pieces(),
+            Color::Black => self.board.all_white_pieces(),
+        };
+        let en_passant = self.board.en_passant.map_or(Bitboard(0), square_mask);
+        self.noisy_only = true;
+        self.target_mask = enemies | en_passant;
+        self.gen_pseudo_moves();
+        self.noisy_only = false;
+        self.target_mask = Bitboard(u64::MAX);
+    }
 
-        let spot1 = king_clip_file_a << 7;
-        let spot2 = king_bitboard << 8;
-        let spot3 = king_clip_file_h << 9;
-        let spot4 = king_clip_file_h << 1;
-        let spot5 = king_clip_file_h >> 7;
-        let spot6 = king_bitboard >> 8;
-        let spot7 = king_clip_file_a >> 9;
-        let spot8 = king_clip_file_a >> 1;
+    pub fn gen_white_moves(&mut self) {
+        self.gen_pawn_moves(Color::White);
+        self.gen_white_knight_moves();
+        self.gen_white_rook_moves();
+        self.gen_white_bishop_moves();
+        self.gen_white_queen_moves();
+        self.gen_white_king_moves();
+    }
 
-        let moved_king = spot1 | spot2 | spot3 | spot4 | spot5 | spot6 | spot7 | spot8;
+    pub fn gen_black_moves(&mut self) {
+        self.gen_pawn_moves(Color::Black);
+        self.gen_black_knight_moves();
+        self.gen_black_rook_moves();
+        self.gen_black_bishop_moves();
+        self.gen_black_queen_moves();
+        self.gen_black_king_moves();
+    }
 
+    /// Generates the pawn moves of `color`: single and double pushes,
+    /// captures on both sides, promotions and en passant.
+    pub fn gen_pawn_moves(&mut self, color: Color) {
+        // Offsets are from the point of view of White, mirrored for Black
+        let (pawns, enemies, forward, double_rank, last_rank) = match color {
+            Color::White => (
+                self.board.white_pawn.bitboard,
+                self.board.all_black_pieces(),
+                8,
+                MASK_RANK[3],
+                MASK_RANK[7],
+            ),
+            Color::Black => (
+                self.board.black_pawn.bitboard,
+                self.board.all_white_pieces(),
+                -8,
+                MASK_RANK[4],
+                MASK_RANK[0],
+            ),
+        };
         let free_squares = !self.board.all_pieces();
-        let mut no_attack = moved_king & free_squares;
-        let mut attacks = moved_king & self.board.all_black_pieces();
 
-        while no_attack != 0 {
-            let to = no_attack.pop_lsb().unwrap();
+        let single_pushes = pawns.shift_signed(forward) & free_squares;
+        let pushes = single_pushes & self.target_mask & (last_rank | self.quiet_mask());
+        self.push_pawn_moves(color, pushes, forward, last_rank);
 
-            let m = Move {
-                piece_kind: Kind::King,
-                piece_color: Color::White,
-                from: Square::from_usize(king_bitboard.clone().pop_lsb().unwrap()),
+        let mut double_pushes =
+            single_pushes.shift_signed(forward) & free_squares & double_rank & self.quiet_mask();
+        while let Some(to) = double_pushes.pop_lsb() {
+            self.push(Move {
+                piece_kind: Kind::Pawn,
+                piece_color: color,
+                from: Square::from_usize(to.wrapping_add_signed(-2 * isize::from(forward))),
                 to: Square::from_usize(to),
                 casteling: false,
                 promoting_piece: None,
-                double_push: false,
+                double_push: true,
                 en_passant: false,
                 captured_piece: None,
-            };
-            self.pseudo_move_list.push(m);
+            });
         }
 
-        while attacks != 0 {
-            let to = attacks.pop_lsb().unwrap();
-
-            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
-
-            let m = Move {
-                piece_kind: Kind::King,
-                piece_color: Color::White,
-                from: Square::from_usize(king_bitboard.clone().pop_lsb().unwrap()),
-                to: Square::from_usize(to),
-                casteling: false,
-                promoting_piece: None,
-                double_push: false,
-                en_passant: false,
-                captured_piece,
-            };
-            self.pseudo_move_list.push(m);
+        // Towards the a file then the h file, without wrapping around the board.
+        // All the pawns are shifted at once, cheaper than a lookup per pawn.
+        for (offset, no_wrap) in [(forward - 1, CLEAR_FILE[7]), (forward + 1, CLEAR_FILE[0])] {
+            let attacks = pawns.shift_signed(offset) & no_wrap;
+            self.push_pawn_moves(
+                color,
+                attacks & enemies & self.target_mask,
+                offset,
+                last_rank,
+            );
         }
 
-        if self.board.casteling_rights.white_kingside {
-            let no_piece_on_f1 = self.board.get_piece(Square::F1).is_none();
-            let no_piece_on_g1 = self.board.get_piece(Square::G1).is_none();
-            let piece_on_h1 = self.board.get_piece(Square::H1);
-            if no_piece_on_g1
-                && no_piece_on_f1
-                && piece_on_h1.is_some_and(|p| p.color == Color::White && p.kind == Kind::Rook)
-            {
-                let m = Move {
-                    piece_kind: Kind::King,
-                    piece_color: Color::White,
-                    from: Square::E1,
-                    to: Square::G1,
-                    casteling: true,
+        // The pawns able to take en passant are those a pawn of the other
+        // color would attack from the en passant square
+        if let Some(to) = self
+            .board
+            .en_passant
+            .filter(|_| color == self.board.to_move)
+            .filter(|&to| {
+                let taken =
+                    Square::from_usize((to as usize).wrapping_add_signed(-isize::from(forward)));
+                (square_mask(to) | square_mask(taken)) & self.target_mask != 0
+            })
+        {
+            let mut takers = pawn_attacks(color.opposite(), to) & pawns;
+            while let Some(from) = takers.pop_lsb() {
+                self.push(Move {
+                    piece_kind: Kind::Pawn,
+                    piece_color: color,
+                    from: Square::from_usize(from),
+                    to,
+                    casteling: false,
                     promoting_piece: None,
                     double_push: false,
-                    en_passant: false,
-                    captured_piece: None,
-                };
-                self.pseudo_move_list.push(m);
+                    captured_piece: Some(Kind::Pawn),
+                    en_passant: true,
+                });
             }
         }
-        if self.board.casteling_rights.white_queenside {
-            let no_piece_on_b1 = self.board.get_piece(Square::B1).is_none();
-            let no_piece_on_c1 = self.board.get_piece(Square::C1).is_none();
-            let no_piece_on_d1 = self.board.get_piece(Square::D1).is_none();
-            let piece_on_a1 = self.board.get_piece(Square::A1);
-            if no_piece_on_b1
-                && no_piece_on_c1
-                && no_piece_on_d1
-                && piece_on_a1.is_some_and(|p| p.color == Color::White && p.kind == Kind::Rook)
-            {
-                let m = Move {
-                    piece_kind: Kind::King,
-                    piece_color: Color::White,
-                    from: Square::E1,
-                    to: Square::C1,
-                    casteling: true,
-                    promoting_piece: None,
+    }
+
+    /// Pushes the pawn moves landing on `targets`, coming from `offset`
+    /// squares behind, with the four promotions on `last_rank`.
+    fn push_pawn_moves(
+        &mut self,
+        color: Color,
+        mut targets: Bitboard,
+        offset: i8,
+        last_rank: Bitboard,
+    ) {
+        while let Some(to) = targets.pop_lsb() {
+            let from = Square::from_usize(to.wrapping_add_signed(-isize::from(offset)));
+            let captured_piece = self.board.get_piece_kind(Square::from_usize(to));
+            let promotions: &[Option<Kind>] =
+                if last_rank & square_mask(Square::from_usize(to)) == 0 {
+                    &[None]
+                } else {
+                    &[
+                        Some(Kind::Queen),
+                        Some(Kind::Rook),
+                        Some(Kind::Bishop),
+                        Some(Kind::Knight),
+                    ]
+                };
+            for &promoting_piece in promotions {
+                self.push(Move {
+                    piece_kind: Kind::Pawn,
+                    piece_color: color,
+                    from,
+                    to: Square::from_usize(to),
+                    casteling: false,
+                    promoting_piece,
                     double_push: false,
                     en_passant: false,
-                    captured_piece: None,
-                };
-                self.pseudo_move_list.push(m);
+                    captured_piece,
+                });
             }
         }
     }
 
-    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
-    pub fn gen_black_king_moves(&mut self) {
-        // Square nums
-        //     . . . . .
-        //     . 1 2 3 .
-        //     . 8 K 4 .
-        //     . 7 6 5 .
-        //     . . . . .
-
-        let king_bitboard = self.board.black_king.bitboard;
-
-        // We need to clip the h and a file of the king to calculate the sport 1, 3, 4, 5, 7 and 8
-        // to avoid king teleportation to the other side of the board
-        let king_clip_file_h = king_bitboard & CLEAR_FILE[7];
-        let king_clip_file_a = king_bitboard & CLEAR_FILE[0];
-
-        let spot1 = king_clip_file_a << 7;
-        let spot2 = king_bitboard << 8;
-        let spot3 = king_clip_file_h << 9;
-        let spot4 = king_clip_file_h << 1;
-        let spot5 = king_clip_file_h >> 7;
-        let spot6 = king_bitboard >> 8;
-        let spot7 = king_clip_file_a >> 9;
-        let spot8 = king_clip_file_a >> 1;
+    pub fn gen_white_king_moves(&mut self) {
+        self.gen_king_steps(Color::White);
+        self.gen_castling_moves(Color::White);
+    }
 
-        let moved_king = spot1 | spot2 | spot3 | spot4 | spot5 | spot6 | spot7 | spot8;
+    /// Generates the moves of the king of `color` to its neighbouring
+    /// squares, castling aside.
+    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
+    fn gen_king_steps(&mut self, color: Color) {
+        let Some(from) = self.king_of(color) else {
+            return;
+        };
+        let enemies = match color {
+            Color::White => self.board.all_black_pieces(),
+            Color::Black => self.board.all_white_pieces(),
+        };
+        let moved_king = KING_ATTACKS[from as usize];
 
         let free_squares = !self.board.all_pieces();
-        let mut no_attack = moved_king & free_squares;
-        let mut attacks = moved_king & self.board.all_white_pieces();
+        let mut no_attack = moved_king & free_squares & self.quiet_mask();
+        let mut attacks = moved_king & enemies & self.target_mask;
 
         while no_attack != 0 {
             let to = no_attack.pop_lsb().unwrap();
 
             let m = Move {
                 piece_kind: Kind::King,
-                piece_color: Color::Black,
-                from: Square::from_usize(king_bitboard.clone().pop_lsb().unwrap()),
+                piece_color: color,
+                from,
                 to: Square::from_usize(to),
                 casteling: false,
                 promoting_piece: None,
@@ -921,7 +832,7 @@ impl<'a> MoveGen<'a> {
                 en_passant: false,
                 captured_piece: None,
             };
-            self.pseudo_move_list.push(m);
+            self.push(m);
         }
 
         while attacks != 0 {
@@ -931,8 +842,8 @@ impl<'a> MoveGen<'a> {
 
             let m = Move {
                 piece_kind: Kind::King,
-                piece_color: Color::Black,
-                from: Square::from_usize(king_bitboard.clone().pop_lsb().unwrap()),
+                piece_color: color,
+                from,
                 to: Square::from_usize(to),
                 casteling: false,
                 promoting_piece: None,
@@ -940,58 +851,76 @@ impl<'a> MoveGen<'a> {
                 en_passant: false,
                 captured_piece,
             };
-            self.pseudo_move_list.push(m);
+            self.push(m);
         }
+    }
 
-        if self.board.casteling_rights.black_kingside {
-            let no_piece_on_f8 = self.board.get_piece(Square::F8).is_none();
-            let no_piece_on_g8 = self.board.get_piece(Square::G8).is_none();
-            let piece_on_h8 = self.board.get_piece(Square::H8);
-            if no_piece_on_g8
-                && no_piece_on_f8
-                && piece_on_h8.is_some_and(|p| p.color == Color::Black && p.kind == Kind::Rook)
-            {
-                let m = Move {
-                    piece_kind: Kind::King,
-                    piece_color: Color::Black,
-                    from: Square::E8,
-                    to: Square::G8,
-                    casteling: true,
-                    promoting_piece: None,
-                    double_push: false,
-                    en_passant: false,
-                    captured_piece: None,
-                };
-                self.pseudo_move_list.push(m);
-            }
+    /// The square of the king of `color`, `None` on a board without one,
+    /// which then simply has no king moves
+    fn king_of(&self, color: Color) -> Option<Square> {
+        let (.., king) = pieces_of(self.board, color);
+        king.clone().pop_lsb().map(Square::from_usize)
+    }
+
+    /// Generates the castling moves of `color`, in standard chess or
+    /// Chess960: the king lands on the g or c file and the rook next to it.
+    /// Every square crossed by the king or the rook must be empty, apart
+    /// from themselves; whether the king crosses an attacked square is left
+    /// to `is_legal`.
+    #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
+    pub fn gen_castling_moves(&mut self, color: Color) {
+        if self.noisy_only {
+            return;
         }
-        if self.board.casteling_rights.black_queenside {
-            let no_piece_on_b8 = self.board.get_piece(Square::B8).is_none();
-            let no_piece_on_c8 = self.board.get_piece(Square::C8).is_none();
-            let no_piece_on_d8 = self.board.get_piece(Square::D8).is_none();
-            let piece_on_a8 = self.board.get_piece(Square::A8);
-            if no_piece_on_b8
-                && no_piece_on_c8
-                && no_piece_on_d8
-                && piece_on_a8.is_some_and(|p| p.color == Color::Black && p.kind == Kind::Rook)
-            {
-                let m = Move {
-                    piece_kind: Kind::King,
-                    piece_color: Color::Black,
-                    from: Square::E8,
-                    to: Square::C8,
-                    casteling: true,
-                    promoting_piece: None,
-                    double_push: false,
-                    en_passant: false,
-                    captured_piece: None,
-                };
-                self.pseudo_move_list.push(m);
+        let rank = match color {
+            Color::White => 0,
+            Color::Black => 56,
+        };
+        let Some(king_from) = self.king_of(color) else {
+            return;
+        };
+        if usize::from(king_from.rank()) != rank / 8 {
+            return;
+        }
+        for (side, king_to, rook_to) in [
+            (CastleSide::King, rank + 6, rank + 5),
+            (CastleSide::Queen, rank + 2, rank + 3),
+        ] {
+            let Some(rook_from) = self.board.castling_rook_square(color, side) else {
+                continue;
+            };
+            let others =
+                self.board.all_pieces() & !square_mask(king_from) & !square_mask(rook_from);
+            let path =
+                rank_span(king_from as usize, king_to) | rank_span(rook_from as usize, rook_to);
+            if path & others != 0 || self.target_mask & Bitboard(1 << king_to) == 0 {
+                continue;
             }
+            self.push(Move {
+                piece_kind: Kind::King,
+                piece_color: color,
+                from: king_from,
+                to: Square::from_usize(king_to),
+                casteling: true,
+                promoting_piece: None,
+                double_push: false,
+                en_passant: false,
+                captured_piece: None,
+            });
         }
     }
 
+    pub fn gen_black_king_moves(&mut self) {
+        self.gen_king_steps(Color::Black);
+        self.gen_castling_moves(Color::Black);
+    }
+
     pub fn gen_knight_moves(&self, knight_loc: Bitboard) -> Bitboard {
+        Self::knight_attacks(knight_loc)
+    }
+
+    /// The squares a knight jumps to from the knight(s) of `knight_loc`.
+    fn knight_attacks(knight_loc: Bitboard) -> Bitboard {
         // Square nums
         //     . 8 . 1 .
         //     7 . . . 2
@@ -1022,13 +951,13 @@ impl<'a> MoveGen<'a> {
         let mut knights_bitboard = self.board.white_knight.bitboard;
         while knights_bitboard != 0 {
             let knight_pos = knights_bitboard.pop_lsb().unwrap();
-            let knight_bitboard = square_mask(Square::from_usize(knight_pos));
+            let from = Square::from_usize(knight_pos);
 
-            let moved_knight = self.gen_knight_moves(knight_bitboard);
+            let moved_knight = KNIGHT_ATTACKS[knight_pos];
 
             let free_squares = !self.board.all_pieces();
-            let mut no_attack = moved_knight & free_squares;
-            let mut attacks = moved_knight & self.board.all_black_pieces();
+            let mut no_attack = moved_knight & free_squares & self.quiet_mask();
+            let mut attacks = moved_knight & self.board.all_black_pieces() & self.target_mask;
 
             while no_attack != 0 {
                 let to = no_attack.pop_lsb().unwrap();
@@ -1036,7 +965,7 @@ impl<'a> MoveGen<'a> {
                 let m = Move {
                     piece_kind: Kind::Knight,
                     piece_color: Color::White,
-                    from: Square::from_usize(knight_bitboard.clone().pop_lsb().unwrap()),
+                    from,
                     to: Square::from_usize(to),
                     casteling: false,
                     promoting_piece: None,
@@ -1044,7 +973,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece: None,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
 
             while attacks != 0 {
@@ -1055,7 +984,7 @@ impl<'a> MoveGen<'a> {
                 let m = Move {
                     piece_kind: Kind::Knight,
                     piece_color: Color::White,
-                    from: Square::from_usize(knight_bitboard.clone().pop_lsb().unwrap()),
+                    from,
                     to: Square::from_usize(to),
                     casteling: false,
                     promoting_piece: None,
@@ -1063,7 +992,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1073,13 +1002,13 @@ impl<'a> MoveGen<'a> {
         let mut knights_bitboard = self.board.black_knight.bitboard;
         while knights_bitboard != 0 {
             let knight_pos = knights_bitboard.pop_lsb().unwrap();
-            let knight_bitboard = square_mask(Square::from_usize(knight_pos));
+            let from = Square::from_usize(knight_pos);
 
-            let moved_knight = self.gen_knight_moves(knight_bitboard);
+            let moved_knight = KNIGHT_ATTACKS[knight_pos];
 
             let free_squares = !self.board.all_pieces();
-            let mut no_attack = moved_knight & free_squares;
-            let mut attacks = moved_knight & self.board.all_white_pieces();
+            let mut no_attack = moved_knight & free_squares & self.quiet_mask();
+            let mut attacks = moved_knight & self.board.all_white_pieces() & self.target_mask;
 
             while no_attack != 0 {
                 let to = no_attack.pop_lsb().unwrap();
@@ -1087,7 +1016,7 @@ impl<'a> MoveGen<'a> {
                 let m = Move {
                     piece_kind: Kind::Knight,
                     piece_color: Color::Black,
-                    from: Square::from_usize(knight_bitboard.clone().pop_lsb().unwrap()),
+                    from,
                     to: Square::from_usize(to),
                     casteling: false,
                     promoting_piece: None,
@@ -1095,7 +1024,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece: None,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
 
             while attacks != 0 {
@@ -1106,7 +1035,7 @@ impl<'a> MoveGen<'a> {
                 let m = Move {
                     piece_kind: Kind::Knight,
                     piece_color: Color::Black,
-                    from: Square::from_usize(knight_bitboard.clone().pop_lsb().unwrap()),
+                    from,
                     to: Square::from_usize(to),
                     casteling: false,
                     promoting_piece: None,
@@ -1114,7 +1043,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1122,13 +1051,15 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_white_bishop_moves(&mut self) {
         let mut bishops = self.board.white_bishop.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_white_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while bishops != 0 {
             let bishop_pos = bishops.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(bishop_pos))
-                & !Bitboard(1 << bishop_pos);
-            let mut moves =
-                BISHOP_MAGICS[bishop_pos].find_attack(blockers) & !self.board.all_white_pieces();
+            let blockers = occ_without_self(occupancy, bishop_pos)
+                & generate_bishop_attack_mask(Square::from_usize(bishop_pos));
+            let mut moves = BISHOP_MAGICS[bishop_pos].find_attack(blockers) & targets;
             while moves != 0 {
                 let to = moves.pop_lsb().unwrap();
 
@@ -1145,7 +1076,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1153,13 +1084,15 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_black_bishop_moves(&mut self) {
         let mut bishops = self.board.black_bishop.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_black_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while bishops != 0 {
             let bishop_pos = bishops.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(bishop_pos))
-                & !Bitboard(1 << bishop_pos);
-            let mut moves =
-                BISHOP_MAGICS[bishop_pos].find_attack(blockers) & !self.board.all_black_pieces();
+            let blockers = occ_without_self(occupancy, bishop_pos)
+                & generate_bishop_attack_mask(Square::from_usize(bishop_pos));
+            let mut moves = BISHOP_MAGICS[bishop_pos].find_attack(blockers) & targets;
             while moves != 0 {
                 let to = moves.pop_lsb().unwrap();
 
@@ -1176,7 +1109,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1184,13 +1117,15 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_white_rook_moves(&mut self) {
         let mut rooks = self.board.white_rook.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_white_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while rooks != 0 {
             let rook_pos = rooks.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(rook_pos))
-                & !Bitboard(1 << rook_pos);
-            let mut moves =
-                ROOK_MAGICS[rook_pos].find_attack(blockers) & !self.board.all_white_pieces();
+            let blockers = occ_without_self(occupancy, rook_pos)
+                & generate_rook_attack_mask(Square::from_usize(rook_pos));
+            let mut moves = ROOK_MAGICS[rook_pos].find_attack(blockers) & targets;
             while moves != 0 {
                 let to = moves.pop_lsb().unwrap();
 
@@ -1207,7 +1142,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1215,13 +1150,15 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_black_rook_moves(&mut self) {
         let mut rooks = self.board.black_rook.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_black_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while rooks != 0 {
             let rook_pos = rooks.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(rook_pos))
-                & !Bitboard(1 << rook_pos);
-            let mut moves =
-                ROOK_MAGICS[rook_pos].find_attack(blockers) & !self.board.all_black_pieces();
+            let blockers = occ_without_self(occupancy, rook_pos)
+                & generate_rook_attack_mask(Square::from_usize(rook_pos));
+            let mut moves = ROOK_MAGICS[rook_pos].find_attack(blockers) & targets;
             while moves != 0 {
                 let to = moves.pop_lsb().unwrap();
 
@@ -1238,7 +1175,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1246,18 +1183,20 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_white_queen_moves(&mut self) {
         let mut queens = self.board.white_queen.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_white_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while queens != 0 {
             let queen_pos = queens.pop_lsb().unwrap();
-            let rook_blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let mut bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers)
-                & !self.board.all_white_pieces();
-            let mut rook_moves =
-                ROOK_MAGICS[queen_pos].find_attack(rook_blockers) & !self.board.all_white_pieces();
+            // Shared by the rook and bishop lookups
+            let occupancy = occ_without_self(occupancy, queen_pos);
+            let rook_blockers =
+                occupancy & generate_rook_attack_mask(Square::from_usize(queen_pos));
+            let bishop_blockers =
+                occupancy & generate_bishop_attack_mask(Square::from_usize(queen_pos));
+            let mut bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers) & targets;
+            let mut rook_moves = ROOK_MAGICS[queen_pos].find_attack(rook_blockers) & targets;
             while rook_moves != 0 {
                 let to = rook_moves.pop_lsb().unwrap();
 
@@ -1274,7 +1213,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
             while bishop_moves != 0 {
                 let to = bishop_moves.pop_lsb().unwrap();
@@ -1292,7 +1231,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
@@ -1300,18 +1239,20 @@ impl<'a> MoveGen<'a> {
     #[allow(clippy::missing_panics_doc, reason = "it is not supposed to panic")]
     pub fn gen_black_queen_moves(&mut self) {
         let mut queens = self.board.black_queen.bitboard;
+        let occupancy = self.board.all_pieces();
+        let targets = !self.board.all_black_pieces()
+            & self.target_mask
+            & (self.board.all_pieces() | self.quiet_mask());
         while queens != 0 {
             let queen_pos = queens.pop_lsb().unwrap();
-            let rook_blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let mut bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers)
-                & !self.board.all_black_pieces();
-            let mut rook_moves =
-                ROOK_MAGICS[queen_pos].find_attack(rook_blockers) & !self.board.all_black_pieces();
+            // Shared by the rook and bishop lookups
+            let occupancy = occ_without_self(occupancy, queen_pos);
+            let rook_blockers =
+                occupancy & generate_rook_attack_mask(Square::from_usize(queen_pos));
+            let bishop_blockers =
+                occupancy & generate_bishop_attack_mask(Square::from_usize(queen_pos));
+            let mut bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers) & targets;
+            let mut rook_moves = ROOK_MAGICS[queen_pos].find_attack(rook_blockers) & targets;
             while rook_moves != 0 {
                 let to = rook_moves.pop_lsb().unwrap();
 
@@ -1328,7 +1269,7 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
             while bishop_moves != 0 {
                 let to = bishop_moves.pop_lsb().unwrap();
@@ -1346,173 +1287,372 @@ impl<'a> MoveGen<'a> {
                     en_passant: false,
                     captured_piece,
                 };
-                self.pseudo_move_list.push(m);
+                self.push(m);
             }
         }
     }
 
-    fn is_square_under_white_attack(&self, square: Square) -> bool {
-        let position = square_mask(square);
+    /// Whether `square` is attacked by White, with `occupancy` used as the
+    /// blockers of the sliding pieces
+    fn is_square_under_white_attack_with(&self, square: Square, occupancy: Bitboard) -> bool {
+        self.attackers_to_with(square, Color::White, occupancy) != 0
+    }
+
+    /// Whether `square` is attacked by Black, with `occupancy` used as the
+    /// blockers of the sliding pieces
+    fn is_square_under_black_attack_with(&self, square: Square, occupancy: Bitboard) -> bool {
+        self.attackers_to_with(square, Color::Black, occupancy) != 0
+    }
+
+    /// The squares next to the king(s) of `king_loc`.
+    fn king_attacks(king_loc: Bitboard) -> Bitboard {
+        // Square nums
+        //     . . . . .
+        //     . 1 2 3 .
+        //     . 8 K 4 .
+        //     . 7 6 5 .
+        //     . . . . .
+
+        // Clip the a and h files to avoid wrapping around the board
+        let king_clip_file_h = king_loc & CLEAR_FILE[7];
+        let king_clip_file_a = king_loc & CLEAR_FILE[0];
+
+        (king_clip_file_a << 7)
+            | (king_loc << 8)
+            | (king_clip_file_h << 9)
+            | (king_clip_file_h << 1)
+            | (king_clip_file_h >> 7)
+            | (king_loc >> 8)
+            | (king_clip_file_a >> 9)
+            | (king_clip_file_a >> 1)
+    }
+
+    /// The pieces of `by` attacking `square`, with `occupancy` used as the
+    /// blockers of the sliding pieces. Attacks are looked up from `square`:
+    /// a knight on it would attack the knights attacking it, and so on.
+    pub fn attackers_to_with(&self, square: Square, by: Color, occupancy: Bitboard) -> Bitboard {
+        attackers_to(self.board, square, by, occupancy)
+    }
+
+    pub fn is_square_under_attack(&self, square: Square, by: Color) -> bool {
+        self.board.is_square_attacked(square, by)
+    }
+
+    /// Whether `square` is attacked by `by` once the king of the other side
+    /// is removed from the board, so that the king can't block a ray
+    /// attacking the square it escapes to.
+    pub fn is_king_escape_attacked(&self, square: Square, by: Color) -> bool {
+        let occupancy = match by {
+            Color::White => self.board.all_pieces() & !self.board.black_king.bitboard,
+            Color::Black => self.board.all_pieces() & !self.board.white_king.bitboard,
+        };
+        match by {
+            Color::White => self.is_square_under_white_attack_with(square, occupancy),
+            Color::Black => self.is_square_under_black_attack_with(square, occupancy),
+        }
+    }
+}
+
+/// The squares of a rank from `a` to `b`, both included
+fn rank_span(a: usize, b: usize) -> Bitboard {
+    let (low, high) = (a.min(b), a.max(b));
+    Bitboard((u64::MAX >> (63 - high)) & (u64::MAX << low))
+}
 
-        // A bitboard representing all pawn left attack
-        let pawn_left_attacks = (self.board.white_pawn.bitboard << 7) & CLEAR_FILE[7];
-        let pawn_right_attacks = (self.board.white_pawn.bitboard << 9) & CLEAR_FILE[0];
+/// The squares a knight attacks, by square
+pub static KNIGHT_ATTACKS: LazyLock<[Bitboard; 64]> = LazyLock::new(|| {
+    std::array::from_fn(|sq| MoveGen::knight_attacks(square_mask(Square::from_usize(sq))))
+});
+
+/// The squares a king attacks, by square
+pub static KING_ATTACKS: LazyLock<[Bitboard; 64]> = LazyLock::new(|| {
+    std::array::from_fn(|sq| MoveGen::king_attacks(square_mask(Square::from_usize(sq))))
+});
+
+/// The squares a White pawn attacks, by square
+pub static WHITE_PAWN_ATTACKS: LazyLock<[Bitboard; 64]> = LazyLock::new(|| {
+    std::array::from_fn(|sq| {
+        let pawn = square_mask(Square::from_usize(sq));
+        ((pawn << 7) & CLEAR_FILE[7]) | ((pawn << 9) & CLEAR_FILE[0])
+    })
+});
+
+/// The squares a Black pawn attacks, by square
+pub static BLACK_PAWN_ATTACKS: LazyLock<[Bitboard; 64]> = LazyLock::new(|| {
+    std::array::from_fn(|sq| {
+        let pawn = square_mask(Square::from_usize(sq));
+        ((pawn >> 7) & CLEAR_FILE[0]) | ((pawn >> 9) & CLEAR_FILE[7])
+    })
+});
+
+/// The squares a pawn of `color` on `square` attacks
+pub fn pawn_attacks(color: Color, square: Square) -> Bitboard {
+    match color {
+        Color::White => WHITE_PAWN_ATTACKS[square as usize],
+        Color::Black => BLACK_PAWN_ATTACKS[square as usize],
+    }
+}
 
-        let king_bitboard = self.board.white_king.bitboard;
+/// The squares strictly between `a` and `b` if they share a rank, a file
+/// or a diagonal, none otherwise
+fn squares_between(a: Square, b: Square) -> Bitboard {
+    let (a, b) = (a as usize, b as usize);
+    [
+        &NORTH_RAY,
+        &EAST_RAY,
+        &SOUTH_RAY,
+        &WEST_RAY,
+        &NORTH_EAST_RAY,
+        &NORTH_WEST_RAY,
+        &SOUTH_EAST_RAY,
+        &SOUTH_WEST_RAY,
+    ]
+    .into_iter()
+    .find(|ray| ray[a] & Bitboard(1 << b) != 0)
+    .map_or(Bitboard(0), |ray| ray[a] & !ray[b] & !Bitboard(1 << b))
+}
 
-        let king_clip_file_h = king_bitboard & CLEAR_FILE[7];
-        let king_clip_file_a = king_bitboard & CLEAR_FILE[0];
+/// The pieces of `by` attacking `square`, see `MoveGen::attackers_to_with`.
+pub(crate) fn attackers_to(
+    board: &Board,
+    square: Square,
+    by: Color,
+    occupancy: Bitboard,
+) -> Bitboard {
+    let (pawns, knights, bishops, rooks, queens, king) = pieces_of(board, by);
+
+    // The squares a pawn of `by` attacks `square` from are those a pawn of
+    // the other color on `square` would attack
+    let pawn_sources = pawn_attacks(by.opposite(), square);
+
+    let sq = square as usize;
+    let occupancy = occ_without_self(occupancy, sq);
+    let diagonal = BISHOP_MAGICS[sq].find_attack(occupancy & generate_bishop_attack_mask(square));
+    let straight = ROOK_MAGICS[sq].find_attack(occupancy & generate_rook_attack_mask(square));
+
+    (pawn_sources & pawns)
+        | (KNIGHT_ATTACKS[square as usize] & knights)
+        | (KING_ATTACKS[square as usize] & king)
+        | (diagonal & (bishops | queens))
+        | (straight & (rooks | queens))
+}
 
-        let spot1 = king_clip_file_a << 7;
-        let spot2 = king_bitboard << 8;
-        let spot3 = king_clip_file_h << 9;
-        let spot4 = king_clip_file_h << 1;
-        let spot5 = king_clip_file_h >> 7;
-        let spot6 = king_bitboard >> 8;
-        let spot7 = king_clip_file_a >> 9;
-        let spot8 = king_clip_file_a >> 1;
+/// Every square attacked by a piece of `by`, with `occupancy` used as the
+/// blockers of the sliding pieces
+pub(crate) fn attacked_squares(board: &Board, by: Color, occupancy: Bitboard) -> Bitboard {
+    let (pawns, knights, bishops, rooks, queens, king) = pieces_of(board, by);
+    let mut attacked = Bitboard(0);
+    for square in pawns.iter_squares() {
+        attacked |= pawn_attacks(by, square);
+    }
+    for square in knights.iter_squares() {
+        attacked |= KNIGHT_ATTACKS[square as usize];
+    }
+    for square in king.iter_squares() {
+        attacked |= KING_ATTACKS[square as usize];
+    }
+    for square in (bishops | queens).iter_squares() {
+        let blockers =
+            occ_without_self(occupancy, square as usize) & generate_bishop_attack_mask(square);
+        attacked |= BISHOP_MAGICS[square as usize].find_attack(blockers);
+    }
+    for square in (rooks | queens).iter_squares() {
+        let blockers =
+            occ_without_self(occupancy, square as usize) & generate_rook_attack_mask(square);
+        attacked |= ROOK_MAGICS[square as usize].find_attack(blockers);
+    }
+    attacked
+}
 
-        let king_attacks = spot1 | spot2 | spot3 | spot4 | spot5 | spot6 | spot7 | spot8;
-        let mut knight_attacks = Bitboard(0);
-        let mut knights = self.board.white_knight.bitboard;
-        while knights != 0 {
-            let knight_pos = knights.pop_lsb().unwrap();
-            let moves = self.gen_knight_moves(square_mask(Square::from_usize(knight_pos)));
-            knight_attacks = knight_attacks | moves;
-        }
+/// The squares a piece of `kind` and `color` on `square` attacks, with
+/// `occupancy` used as the blockers of the sliding pieces
+pub(crate) fn piece_attacks(
+    kind: Kind,
+    color: Color,
+    square: Square,
+    occupancy: Bitboard,
+) -> Bitboard {
+    let sq = square as usize;
+    let occupancy = occ_without_self(occupancy, sq);
+    let diagonal =
+        || BISHOP_MAGICS[sq].find_attack(occupancy & generate_bishop_attack_mask(square));
+    let straight = || ROOK_MAGICS[sq].find_attack(occupancy & generate_rook_attack_mask(square));
+    match kind {
+        Kind::Pawn => pawn_attacks(color, square),
+        Kind::Knight => KNIGHT_ATTACKS[sq],
+        Kind::King => KING_ATTACKS[sq],
+        Kind::Bishop => diagonal(),
+        Kind::Rook => straight(),
+        Kind::Queen => diagonal() | straight(),
+    }
+}
 
-        let mut bishop_attacks = Bitboard(0);
+/// The pawns, knights, bishops, rooks, queens and king of `color`
+fn pieces_of(
+    board: &Board,
+    color: Color,
+) -> (Bitboard, Bitboard, Bitboard, Bitboard, Bitboard, Bitboard) {
+    match color {
+        Color::White => (
+            board.white_pawn.bitboard,
+            board.white_knight.bitboard,
+            board.white_bishop.bitboard,
+            board.white_rook.bitboard,
+            board.white_queen.bitboard,
+            board.white_king.bitboard,
+        ),
+        Color::Black => (
+            board.black_pawn.bitboard,
+            board.black_knight.bitboard,
+            board.black_bishop.bitboard,
+            board.black_rook.bitboard,
+            board.black_queen.bitboard,
+            board.black_king.bitboard,
+        ),
+    }
+}
 
-        let mut bishops = self.board.white_bishop.bitboard;
-        while bishops != 0 {
-            let bishop_pos = bishops.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(bishop_pos))
-                & !Bitboard(1 << bishop_pos);
-            let moves = BISHOP_MAGICS[bishop_pos].find_attack(blockers);
-            bishop_attacks = bishop_attacks | moves;
-        }
+/// The occupancy seen by a slider on `pos`, which does not block itself
+fn occ_without_self(occupancy: Bitboard, pos: usize) -> Bitboard {
+    occupancy & !Bitboard(1 << pos)
+}
 
-        let mut rook_attacks = Bitboard(0);
-        let mut rooks = self.board.white_rook.bitboard;
-        while rooks != 0 {
-            let rook_pos = rooks.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(rook_pos))
-                & !Bitboard(1 << rook_pos);
-            let moves = ROOK_MAGICS[rook_pos].find_attack(blockers);
-            rook_attacks = rook_attacks | moves;
-        }
+/// Stage of a `StagedMoveGen`
+#[derive(Copy, Clone, PartialEq)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    Captures,
+    GenerateQuiets,
+    Quiets,
+}
 
-        let mut queen_attacks = Bitboard(0);
-        let mut queens = self.board.white_queen.bitboard;
-        while queens != 0 {
-            let queen_pos = queens.pop_lsb().unwrap();
-            let rook_blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers);
-            let rook_moves = ROOK_MAGICS[queen_pos].find_attack(rook_blockers);
-            queen_attacks = queen_attacks | rook_moves | bishop_moves;
-        }
-
-        position
-            & (pawn_left_attacks
-                | pawn_right_attacks
-                | king_attacks
-                | bishop_attacks
-                | knight_attacks
-                | rook_attacks
-                | queen_attacks)
-            != 0
-    }
-
-    fn is_square_under_black_attack(&self, square: Square) -> bool {
-        let position = square_mask(square);
-
-        // A bitboard representing all pawn left attack
-        let pawn_left_attacks = (self.board.black_pawn.bitboard >> 7) & CLEAR_FILE[0];
-        let pawn_right_attacks = (self.board.black_pawn.bitboard >> 9) & CLEAR_FILE[7];
-
-        let king_bitboard = self.board.black_king.bitboard;
-
-        let king_clip_file_h = king_bitboard & CLEAR_FILE[7];
-        let king_clip_file_a = king_bitboard & CLEAR_FILE[0];
-
-        let spot1 = king_clip_file_a << 7;
-        let spot2 = king_bitboard << 8;
-        let spot3 = king_clip_file_h << 9;
-        let spot4 = king_clip_file_h << 1;
-        let spot5 = king_clip_file_h >> 7;
-        let spot6 = king_bitboard >> 8;
-        let spot7 = king_clip_file_a >> 9;
-        let spot8 = king_clip_file_a >> 1;
-
-        let king_attacks = spot1 | spot2 | spot3 | spot4 | spot5 | spot6 | spot7 | spot8;
-        let mut knight_attacks = Bitboard(0);
-        let mut knights = self.board.black_knight.bitboard;
-        while knights != 0 {
-            let knight_pos = knights.pop_lsb().unwrap();
-            let moves = self.gen_knight_moves(square_mask(Square::from_usize(knight_pos)));
-            knight_attacks = knight_attacks | moves;
-        }
-
-        let mut bishop_attacks = Bitboard(0);
+/// Iterator over the legal moves of a position, for move ordering in a
+/// search: the transposition table move first (if any), then captures and
+/// promotions from the most valuable victim (or promoted piece) by the
+/// least valuable attacker, then the other moves.
+/// Each stage is only generated once the previous one runs out, and
+/// legality is only checked as moves are yielded, so stopping early (e.g.
+/// on a beta cutoff) skips the work for the remaining moves.
+pub struct StagedMoveGen<'a> {
+    move_gen: MoveGen<'a>,
+    scratch: Board,
+    stage: Stage,
+    tt_move: Option<Move>,
+    // Both stored in reverse order, so that popping yields the next move
+    captures: Vec<Move>,
+    quiets: Vec<Move>,
+}
 
-        let mut bishops = self.board.black_bishop.bitboard;
-        while bishops != 0 {
-            let bishop_pos = bishops.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(bishop_pos))
-                & !Bitboard(1 << bishop_pos);
-            let moves = BISHOP_MAGICS[bishop_pos].find_attack(blockers);
-            bishop_attacks = bishop_attacks | moves;
+impl<'a> StagedMoveGen<'a> {
+    pub fn new(board: &'a Board) -> Self {
+        Self {
+            move_gen: MoveGen::new(board),
+            scratch: board.clone(),
+            stage: Stage::TtMove,
+            tt_move: None,
+            captures: Vec::new(),
+            quiets: Vec::new(),
         }
+    }
 
-        let mut rook_attacks = Bitboard(0);
-        let mut rooks = self.board.black_rook.bitboard;
-        while rooks != 0 {
-            let rook_pos = rooks.pop_lsb().unwrap();
-            let blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(rook_pos))
-                & !Bitboard(1 << rook_pos);
-            let moves = ROOK_MAGICS[rook_pos].find_attack(blockers);
-            rook_attacks = rook_attacks | moves;
+    /// Sets the move to try first. It is skipped if it is not legal in
+    /// the position.
+    #[must_use]
+    pub fn with_tt_move(mut self, m: Move) -> Self {
+        self.tt_move = Some(m);
+        self
+    }
+
+    fn is_tt_move(&self, m: &Move) -> bool {
+        self.tt_move.as_ref().is_some_and(|tt| {
+            tt.from == m.from && tt.to == m.to && tt.promoting_piece == m.promoting_piece
+        })
+    }
+
+    /// The TT move as generated in this position, if it is pseudo legal:
+    /// it may come from another position. Only the moves of the piece on
+    /// its origin square are generated to find it.
+    fn find_tt_move(&mut self) -> Option<Move> {
+        let from = self.tt_move.as_ref()?.from;
+        let kind = self.move_gen.board.get_piece_kind(from)?;
+        let start = self.move_gen.pseudo_move_list.len();
+        self.move_gen.gen_piece_moves(self.move_gen.board.to_move, kind);
+        let moves = self.move_gen.pseudo_move_list.split_off(start);
+        moves.into_iter().find(|m| m.from == from && self.is_tt_move(m))
+    }
+
+    /// Generates the captures and promotions, without the TT move, sorted
+    /// so that the best one is popped first.
+    fn generate_captures(&mut self) {
+        let mut captures = self.move_gen.gen_noisy();
+        captures.retain(|m| !self.is_tt_move(m));
+
+        // The king is the last attacker to consider, its value being 0
+        let attacker = |kind: Kind| match kind {
+            Kind::King => i32::MAX,
+            kind => kind.value(),
+        };
+        captures.sort_by_key(|m| {
+            let victim = m.captured_piece.map_or(0, Kind::value);
+            let promotion = m.promoting_piece.map_or(0, Kind::value);
+            (victim + promotion, -attacker(m.piece_kind))
+        });
+        self.captures = captures;
+    }
+
+    /// Generates the other moves, without the TT move, in generation order.
+    fn generate_quiets(&mut self) {
+        let mut quiets = self.move_gen.gen_quiets();
+        quiets.retain(|m| !self.is_tt_move(m));
+        quiets.reverse();
+        self.quiets = quiets;
+    }
+
+    /// Pops moves from `list` until a legal one is found.
+    fn next_legal(move_gen: &MoveGen, scratch: &mut Board, list: &mut Vec<Move>) -> Option<Move> {
+        while let Some(m) = list.pop() {
+            if move_gen.is_legal(&m, scratch) {
+                return Some(m);
+            }
         }
-
-        let mut queen_attacks = Bitboard(0);
-        let mut queens = self.board.black_queen.bitboard;
-        while queens != 0 {
-            let queen_pos = queens.pop_lsb().unwrap();
-            let rook_blockers = self.board.all_pieces()
-                & generate_rook_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_blockers = self.board.all_pieces()
-                & generate_bishop_attack_mask(Square::from_usize(queen_pos))
-                & !Bitboard(1 << queen_pos);
-            let bishop_moves = BISHOP_MAGICS[queen_pos].find_attack(bishop_blockers);
-            let rook_moves = ROOK_MAGICS[queen_pos].find_attack(rook_blockers);
-            queen_attacks = queen_attacks | rook_moves | bishop_moves;
-        }
-
-        position
-            & (pawn_left_attacks
-                | pawn_right_attacks
-                | king_attacks
-                | bishop_attacks
-                | knight_attacks
-                | rook_attacks
-                | queen_attacks)
-            != 0
+        None
     }
+}
 
-    pub fn is_square_under_attack(&self, square: Square, by: Color) -> bool {
-        match by {
-            Color::White => self.is_square_under_white_attack(square),
-            Color::Black => self.is_square_under_black_attack(square),
+impl Iterator for StagedMoveGen<'_> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(m) = self.find_tt_move()
+                        && self.move_gen.is_legal(&m, &mut self.scratch)
+                    {
+                        return Some(m);
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    self.generate_captures();
+                    self.stage = Stage::Captures;
+                }
+                Stage::Captures => {
+                    let m = Self::next_legal(&self.move_gen, &mut self.scratch, &mut self.captures);
+                    if m.is_some() {
+                        return m;
+                    }
+                    self.stage = Stage::GenerateQuiets;
+                }
+                Stage::GenerateQuiets => {
+                    self.generate_quiets();
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    return Self::next_legal(&self.move_gen, &mut self.scratch, &mut self.quiets);
+                }
+            }
         }
     }
 }
@@ -1528,24 +1668,6 @@ mod tests {
         assert_eq!(v.len(), n_move);
     }
 
-    fn perft(board: &Board, depth: u32) -> u64 {
-        if depth == 0 {
-            return 1;
-        }
-
-        let mut nodes = 0;
-        let mut movegen = MoveGen::new(board);
-
-        movegen.gen_legal_moves();
-        for mv in movegen.get_legal_moves() {
-            let mut new_board = board.clone();
-            new_board.do_move(mv);
-            nodes += perft(&new_board, depth - 1);
-        }
-
-        nodes
-    }
-
     #[test]
     fn test_king_center() {
         wrapper("k7/8/8/8/3K4/8/8/8 w - - 0 1", 8);
@@ -1784,10 +1906,854 @@ mod tests {
         wrapper("k6b/Q7/8/8/8/8/8/R3K3 b Q - 0 1", 0);
     }
 
+    // The positions used by the perft tests below
+    const PERFT_FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    #[test]
+    fn test_debug_assert_consistency() {
+        for fen in PERFT_FENS {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            mg.debug_assert_consistency();
+
+            // Also check one ply deeper, to cover more varied positions
+            for m in mg.get_legal_moves() {
+                let mut child = board.clone();
+                child.do_move(m);
+                let mut child_mg = MoveGen::new(&child);
+                child_mg.gen_pseudo_moves();
+                child_mg.debug_assert_consistency();
+            }
+        }
+    }
+
+    #[test]
+    fn test_legal_destinations() {
+        // The bishop on d2 is pinned by the bishop on a5
+        let board = Board::from_fen("4k3/8/8/b7/8/8/3B4/4K3 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        let pin_ray = Bitboard::from_squares(&[Square::C3, Square::B4, Square::A5]);
+        assert!(mg.legal_destinations(Square::D2) == pin_ray);
+
+        // The king is free to move, and empty squares have no destination
+        assert!(mg.legal_destinations(Square::E1) != 0);
+        assert!(mg.legal_destinations(Square::H4) == 0);
+    }
+
+    #[test]
+    fn test_has_legal_capture() {
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert!(MoveGen::new(&board).has_legal_capture());
+
+        assert!(!MoveGen::new(&Board::default()).has_legal_capture());
+
+        // The only capture would leave the king in check
+        let board = Board::from_fen("4k3/4r3/8/2p5/4N3/8/8/4K3 w - - 0 1").unwrap();
+        assert!(!MoveGen::new(&board).has_legal_capture());
+
+        // A promotion counts, and repeated calls leave the lists empty
+        let board = Board::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        assert!(mg.has_legal_capture());
+        assert!(mg.has_legal_capture());
+        assert!(mg.get_pseudo_moves().is_empty());
+        assert!(mg.get_legal_moves().is_empty());
+
+        // Found past the first kinds of pieces: only the king can capture
+        let board = Board::from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        assert!(mg.has_legal_capture());
+        assert!(mg.get_pseudo_moves().is_empty());
+    }
+
+    #[test]
+    fn test_kind_of() {
+        let kind_of = |fen: &str, uci: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            board.parse_uci(uci).unwrap().kind_of()
+        };
+        let start = PERFT_FENS[0];
+        assert_eq!(kind_of(start, "g1f3"), MoveType::Quiet);
+        assert_eq!(kind_of(start, "e2e4"), MoveType::DoublePush);
+
+        let fen = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        assert_eq!(kind_of(fen, "e4d5"), MoveType::Capture);
+
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(kind_of(fen, "e5d6"), MoveType::EnPassant);
+
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(kind_of(fen, "e1g1"), MoveType::Castle);
+
+        let fen = "2r1k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(kind_of(fen, "b7b8q"), MoveType::Promotion);
+        assert_eq!(kind_of(fen, "b7c8q"), MoveType::CapturePromotion);
+    }
+
+    #[test]
+    fn test_king_cannot_retreat_along_checking_ray() {
+        // The rook on e8 checks the king, which must not step to e3 even
+        // though the king itself stands between the rook and e3
+        let board = Board::from_fen("k3r3/8/8/8/4K3/8/8/8 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        let destinations = mg.legal_destinations(Square::E4);
+        assert!(destinations & square_mask(Square::E3) == 0);
+        assert!(destinations & square_mask(Square::E5) == 0);
+        assert!(destinations & square_mask(Square::D3) != 0);
+
+        // Same along a diagonal
+        let board = Board::from_fen("k7/8/8/8/4K3/8/8/7b w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        assert!(mg.legal_destinations(Square::E4) & square_mask(Square::D5) == 0);
+    }
+
+    #[test]
+    fn test_to_uci() {
+        let uci = |fen: &str, uci: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            board.parse_uci(uci).unwrap().to_uci()
+        };
+        assert_eq!(uci(PERFT_FENS[0], "g1f3"), "g1f3");
+
+        let fen = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(uci(fen, "b7b8q"), "b7b8q");
+        assert_eq!(uci(fen, "b7b8n"), "b7b8n");
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(uci(fen, "e8g8"), "e8g8");
+        assert_eq!(uci(fen, "e8c8"), "e8c8");
+
+        // Display uses the same notation
+        let board = Board::default();
+        assert_eq!(board.parse_uci("e2e4").unwrap().to_string(), "e2e4");
+    }
+
+    #[test]
+    fn test_display_and_debug() {
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let m = board.parse_uci("b7b8n").unwrap();
+        assert_eq!(format!("{m}"), "b7b8n");
+
+        let debug = format!("{m:?}");
+        assert!(debug.contains("promoting_piece: Some(Knight)"));
+        assert!(debug.contains("captured_piece: None"));
+        assert!(debug.contains("casteling: false"));
+
+        assert_eq!(m, board.parse_uci("b7b8n").unwrap());
+        assert_ne!(m, board.parse_uci("b7b8q").unwrap());
+    }
+
+    #[test]
+    fn test_to_san_with() {
+        let board = Board::from_fen("2k5/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        let moves = mg.get_legal_moves();
+        for m in moves {
+            assert_eq!(m.to_san_with(&board, moves), m.to_san(&board));
+        }
+
+        // The disambiguation only relies on the given moves
+        let rad1 = board.parse_uci("a1d1").unwrap();
+        assert_eq!(rad1.to_san_with(&board, moves), "Rad1");
+        assert_eq!(rad1.to_san_with(&board, &[]), "Rd1");
+
+        // `legal_moves_san` formats every move against the same list,
+        // generated once, and agrees with `to_san`
+        let board = Board::from_fen("k7/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap();
+        let batched = board.legal_moves_san();
+        assert!(batched.contains(&"Nbd2".to_string()));
+        let moves: Vec<Move> = board.legal_moves().collect();
+        let one_by_one: Vec<String> = moves.iter().map(|m| m.to_san(&board)).collect();
+        assert_eq!(batched, one_by_one);
+        let with_list: Vec<String> = moves.iter().map(|m| m.to_san_with(&board, &moves)).collect();
+        assert_eq!(batched, with_list);
+
+        // Without the other knight in the list, no disambiguation is needed
+        let nbd2 = board.parse_uci("b1d2").unwrap();
+        assert_eq!(nbd2.to_san_with(&board, std::slice::from_ref(&nbd2)), "Nd2");
+    }
+
+    #[test]
+    fn test_staged_moves() {
+        for fen in PERFT_FENS {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            let mut expected: Vec<String> = mg.get_legal_moves().iter().map(Move::to_uci).collect();
+
+            let staged: Vec<Move> = board.staged_moves().collect();
+            let mut got: Vec<String> = staged.iter().map(Move::to_uci).collect();
+            expected.sort();
+            got.sort();
+            assert_eq!(got, expected, "{fen}");
+
+            // Captures and promotions come first, most valuable victim (or
+            // promoted piece) first
+            let noisy = |m: &Move| m.captured_piece.is_some() || m.promoting_piece.is_some();
+            let n_captures = staged.iter().take_while(|m| noisy(m)).count();
+            assert!(!staged[n_captures..].iter().any(noisy));
+            let victims: Vec<i32> = staged[..n_captures]
+                .iter()
+                .map(|m| {
+                    m.captured_piece.map_or(0, Kind::value)
+                        + m.promoting_piece.map_or(0, Kind::value)
+                })
+                .collect();
+            assert!(victims.windows(2).all(|w| w[0] >= w[1]));
+        }
+
+        // Kiwipete: the first moves are captures
+        let board = Board::from_fen(PERFT_FENS[1]).unwrap();
+        assert!(board
+            .staged_moves()
+            .take(3)
+            .all(|m| m.captured_piece.is_some()));
+    }
+
+    #[test]
+    fn test_staged_moves_generate_quiets_last() {
+        // Kiwipete: taking the first capture does not generate the quiets
+        let board = Board::from_fen(PERFT_FENS[1]).unwrap();
+        let mut staged = board.staged_moves();
+        assert!(staged.next().unwrap().captured_piece.is_some());
+        assert!(staged.stage == Stage::Captures);
+        assert!(staged.quiets.is_empty());
+
+        // Nor does the TT move, even a quiet one
+        let tt = board.parse_uci("e1g1").unwrap();
+        let mut staged = board.staged_moves().with_tt_move(tt);
+        assert_eq!(staged.next().unwrap().to_uci(), "e1g1");
+        assert!(staged.captures.is_empty() && staged.quiets.is_empty());
+
+        // A capture as TT move is not yielded twice
+        let tt = board.parse_uci("e2a6").unwrap();
+        let moves: Vec<String> = board
+            .staged_moves()
+            .with_tt_move(tt)
+            .map(|m| m.to_uci())
+            .collect();
+        assert_eq!(moves[0], "e2a6");
+        assert_eq!(moves.len(), 48);
+        assert_eq!(moves.iter().filter(|m| *m == "e2a6").count(), 1);
+    }
+
+    #[test]
+    fn test_staged_moves_tt_move() {
+        let board = Board::default();
+        let tt = board.parse_uci("g1f3").unwrap();
+        let moves: Vec<String> = board
+            .staged_moves()
+            .with_tt_move(tt)
+            .map(|m| m.to_uci())
+            .collect();
+        assert_eq!(moves[0], "g1f3");
+        assert_eq!(moves.len(), 20);
+        assert_eq!(moves.iter().filter(|m| *m == "g1f3").count(), 1);
+
+        // A TT move which is not legal here is skipped
+        let tt = Board::from_fen(PERFT_FENS[1])
+            .unwrap()
+            .parse_uci("e2a6")
+            .unwrap();
+        assert_eq!(board.staged_moves().with_tt_move(tt).count(), 20);
+
+        // A quiet promotion is tried with the captures, before the quiets
+        let board = Board::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves: Vec<String> = board.staged_moves().map(|m| m.to_uci()).collect();
+        assert_eq!(moves[..4], ["a7a8q", "a7a8r", "a7a8b", "a7a8n"]);
+    }
+
+    #[test]
+    fn test_fen_round_trip_corpus() {
+        // Besides the perft positions: Chess960 castling fields, en passant
+        // squares for both colors and partial castling rights
+        let others = [
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            "4k3/8/8/8/8/8/8/RK5R w HA - 0 1",
+            "r4k2/8/8/8/8/8/8/5KR1 w G - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/8/Pp6/8/8/4K3 b - a3 0 1",
+            "k7/8/8/8/8/8/8/R3K3 w HQ - 0 1",
+        ];
+        for fen in PERFT_FENS.iter().chain(&others) {
+            let board = Board::from_fen(fen).unwrap();
+            let exported = board.to_fen();
+            let reparsed = Board::from_fen(&exported).unwrap();
+            assert_eq!(reparsed.to_fen(), exported, "{fen}");
+            assert_eq!(reparsed.zobrist_hash(), board.zobrist_hash(), "{fen}");
+
+            // Same placement, side to move and en passant square as the
+            // original, the clocks aside. The castling field may be written
+            // with other letters, but must give the same castling rooks.
+            let fields = |fen: &str| {
+                let fields: Vec<&str> = fen.split_whitespace().collect();
+                [fields[0], fields[1], fields[3]].join(" ")
+            };
+            assert_eq!(fields(&exported), fields(fen), "{fen}");
+            for color in [Color::White, Color::Black] {
+                for side in [CastleSide::King, CastleSide::Queen] {
+                    assert_eq!(
+                        reparsed.castling_rook_square(color, side),
+                        board.castling_rook_square(color, side),
+                        "{fen}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_pawn_moves_matches_reference() {
+        // Pawn moves computed square by square, as a reference
+        fn reference(board: &Board, color: Color) -> Vec<String> {
+            let (pawns, enemies, dir, start, last) = match color {
+                Color::White => (board.white_pawn.bitboard, board.all_black_pieces(), 1, 1, 7),
+                Color::Black => (
+                    board.black_pawn.bitboard,
+                    board.all_white_pieces(),
+                    -1,
+                    6,
+                    0,
+                ),
+            };
+            let at = |file: i32, rank: i32| -> Option<Square> {
+                ((0..8).contains(&file) && (0..8).contains(&rank))
+                    .then(|| Square::from_usize(usize::try_from(rank * 8 + file).unwrap()))
+            };
+            let occupied = |sq: Square| board.all_pieces() & square_mask(sq) != 0;
+            let mut moves = Vec::new();
+            let mut push = |from: Square, to: Square, rank: i32| {
+                let uci = format!("{}{}", from.square_to_str(), to.square_to_str());
+                if rank == last {
+                    moves.extend(["q", "r", "b", "n"].map(|p| format!("{uci}{p}")));
+                } else {
+                    moves.push(uci);
+                }
+            };
+            for from in Square::iter() {
+                if pawns & square_mask(from) == 0 {
+                    continue;
+                }
+                let (file, rank) = from.to_coords();
+                let (file, rank) = (i32::from(file), i32::from(rank));
+                if let Some(to) = at(file, rank + dir).filter(|&sq| !occupied(sq)) {
+                    push(from, to, rank + dir);
+                    let to2 = at(file, rank + 2 * dir).filter(|&sq| rank == start && !occupied(sq));
+                    if let Some(to2) = to2 {
+                        push(from, to2, rank + 2 * dir);
+                    }
+                }
+                for side in [-1, 1] {
+                    let to = at(file + side, rank + dir).filter(|&sq| {
+                        enemies & square_mask(sq) != 0 || board.en_passant == Some(sq)
+                    });
+                    if let Some(to) = to {
+                        push(from, to, rank + dir);
+                    }
+                }
+            }
+            moves.sort();
+            moves
+        }
+
+        for fen in PERFT_FENS.iter().chain(&[
+            "4k3/1P6/8/8/8/8/6p1/4K2R b - - 0 1",
+            "4k3/8/8/pP6/8/8/8/4K3 w - a6 0 1",
+            "4k3/8/8/8/Pp6/8/8/4K3 b - a3 0 1",
+            "4k3/8/8/8/6pP/8/8/4K3 b - h3 0 1",
+        ]) {
+            let board = Board::from_fen(fen).unwrap();
+            for color in [Color::White, Color::Black] {
+                let mut mg = MoveGen::new(&board);
+                mg.gen_pawn_moves(color);
+                let mut got: Vec<String> = mg.get_pseudo_moves().iter().map(Move::to_uci).collect();
+                got.sort();
+                assert_eq!(got, reference(&board, color), "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_capture_moves() {
+        for fen in PERFT_FENS
+            .iter()
+            .chain(&["4k3/1P6/8/8/8/8/6p1/4K2R b - - 0 1"])
+        {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            let mut expected: Vec<String> = mg
+                .get_legal_moves()
+                .iter()
+                .filter(|m| m.captured_piece.is_some() || m.promoting_piece.is_some())
+                .map(Move::to_uci)
+                .collect();
+
+            let mut mg = MoveGen::new(&board);
+            mg.gen_capture_moves();
+            let mut got: Vec<String> = mg.get_capture_moves().iter().map(Move::to_uci).collect();
+            expected.sort();
+            got.sort();
+            assert_eq!(got, expected, "{fen}");
+            // No quiet move was generated on the way
+            assert!(mg.get_pseudo_moves().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        let castles = |fen: &str| -> Vec<String> {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            let mut moves: Vec<String> = mg
+                .get_legal_moves()
+                .iter()
+                .filter(|m| m.casteling)
+                .map(|m| m.to_san(&board))
+                .collect();
+            moves.sort();
+            moves
+        };
+
+        // King already on g1, its rook next to it
+        assert_eq!(
+            castles("1r4kr/8/8/8/8/8/8/1R4KR w HB - 0 1"),
+            ["O-O", "O-O-O"]
+        );
+        // King and rook adjacent on the queenside
+        assert_eq!(castles("4k3/8/8/8/8/8/8/RK5R w HA - 0 1"), ["O-O", "O-O-O"]);
+        // A piece on the squares the rook crosses
+        assert_eq!(
+            castles("4k3/8/8/8/8/8/8/RK1N3R w HA - 0 1"),
+            Vec::<String>::new()
+        );
+        // f1 is attacked, the king would cross it
+        assert_eq!(castles("4kr2/8/8/8/8/8/8/RK5R w HA - 0 1"), ["O-O-O"]);
+        // Only the king's destination has to be safe, not the rook's path
+        assert_eq!(
+            castles("1r2k3/8/8/8/8/8/8/R3K1R1 w GA - 0 1"),
+            ["O-O", "O-O-O"]
+        );
+
+        // The king lands on the home square of its rook, which the move
+        // checks accept
+        let fen = "r4k2/8/8/8/8/8/8/5KR1 w G - 0 1";
+        assert_eq!(castles(fen), ["O-O+"]);
+        let board = Board::from_fen(fen).unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        mg.gen_pseudo_moves();
+        mg.debug_assert_consistency();
+        assert!(mg
+            .get_legal_moves()
+            .iter()
+            .any(|m| m.casteling && m.from == Square::F1 && m.to == Square::G1));
+
+        // The king and rook swap around, and are put back
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/RK5R w HA - 0 1").unwrap();
+        let fen = board.to_fen();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        let castle = mg
+            .get_legal_moves()
+            .iter()
+            .find(|m| m.casteling && m.to == Square::C1)
+            .unwrap()
+            .clone();
+        let undo = board.make_move(&castle);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/2KR3R b - - 1 1");
+        assert!(board.verify_hash());
+        board.unmake_move(&castle, undo);
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_chess960_positions() {
+        for (fen, nodes) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            for (depth, expected) in (1..).zip(nodes) {
+                assert_eq!(board.perft(depth), expected, "{fen} at depth {depth}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_in_check_matches_king_captures() {
+        // In check exactly when the other side, were it to move, has a
+        // pseudo-legal capture of the king
+        fn king_capturable(board: &Board, color: Color) -> bool {
+            let king = board.king_square(color);
+            let other = board.with_side_to_move(color.opposite());
+            let mut mg = MoveGen::new(&other);
+            mg.gen_pseudo_moves();
+            mg.get_pseudo_moves().iter().any(|m| m.to == king)
+        }
+
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for m in board.legal_moves().collect::<Vec<_>>() {
+                let undo = board.make_move(&m);
+                for reply in board.legal_moves().collect::<Vec<_>>() {
+                    let undo = board.make_move(&reply);
+                    for color in [Color::White, Color::Black] {
+                        assert_eq!(
+                            board.is_in_check(color),
+                            king_capturable(&board, color),
+                            "{}",
+                            board.to_fen()
+                        );
+                    }
+                    board.unmake_move(&reply, undo);
+                }
+                board.unmake_move(&m, undo);
+            }
+        }
+    }
+
+    #[test]
+    fn test_en_passant_discovered_check() {
+        // Taking en passant removes both pawns from the rank of the king,
+        // which the rook then attacks
+        for (fen, open) in [
+            (
+                "8/8/8/K1Pp3r/8/8/8/k7 w - d6 0 1",
+                "8/8/8/K1Pp4/8/8/8/k6r w - d6 0 1",
+            ),
+            (
+                "8/8/8/8/R2Pp2k/8/8/K7 b - d3 0 1",
+                "8/8/8/8/3Pp2k/8/8/R6K b - d3 0 1",
+            ),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_pseudo_moves();
+            assert!(mg.get_pseudo_moves().iter().any(|m| m.en_passant));
+            assert!(board.legal_moves().all(|m| !m.en_passant), "{fen}");
+
+            // Without the rook on the rank, the capture is fine
+            let board = Board::from_fen(open).unwrap();
+            assert!(board.legal_moves().any(|m| m.en_passant), "{open}");
+        }
+    }
+
+    // The legal moves, found by trying every pseudo legal move
+    fn legal_by_filtering(board: &Board) -> Vec<Move> {
+        let mut mg = MoveGen::new(board);
+        mg.gen_pseudo_moves();
+        let mut scratch = board.clone();
+        mg.pseudo_move_list
+            .iter()
+            .filter(|m| mg.is_legal(m, &mut scratch))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_evasions() {
+        for fen in [
+            // Bishop check, blocked or captured
+            "4k3/8/8/8/1b6/8/8/RN2K3 w Q - 0 1",
+            // Knight check, which can't be blocked
+            "4k3/8/8/8/8/3n4/8/R3K2R w KQ - 0 1",
+            // Double check
+            "4r1k1/8/8/8/8/5n2/3B4/4K3 w - - 0 1",
+            // Pawn check, answered by taking en passant
+            "4k3/8/8/3pP3/4K3/8/8/8 w - d6 0 1",
+            // Rook check along the rank
+            "8/8/8/8/8/2N5/3Q4/r3K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert!(board.is_in_check(board.to_move), "{fen}");
+            let mut mg = MoveGen::new(&board);
+            mg.gen_legal_moves();
+            assert_eq!(mg.legal_move_list, legal_by_filtering(&board), "{fen}");
+        }
+
+        // Only the king is generated in double check, and nothing landing
+        // elsewhere than on the checker or the checking line otherwise
+        let board = Board::from_fen("4r1k1/8/8/8/8/5n2/3B4/4K3 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_candidates();
+        assert!(mg.get_pseudo_moves().iter().all(|m| m.piece_kind == Kind::King));
+        let board = Board::from_fen("4k3/8/8/8/1b6/8/8/RN2K3 w Q - 0 1").unwrap();
+        let line = Bitboard::from_squares(&[Square::B4, Square::C3, Square::D2]);
+        let mut mg = MoveGen::new(&board);
+        mg.gen_candidates();
+        assert!(mg
+            .get_pseudo_moves()
+            .iter()
+            .all(|m| m.piece_kind == Kind::King || line & square_mask(m.to) != 0));
+
+        // Every check met two plies deep from the reference positions
+        for fen in PERFT_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for m in board.legal_moves().collect::<Vec<_>>() {
+                let undo = board.make_move(&m);
+                for reply in board.legal_moves().collect::<Vec<_>>() {
+                    let undo = board.make_move(&reply);
+                    if board.is_in_check(board.to_move) {
+                        let mut mg = MoveGen::new(&board);
+                        mg.gen_legal_moves();
+                        assert_eq!(mg.legal_move_list, legal_by_filtering(&board));
+                    }
+                    board.unmake_move(&reply, undo);
+                }
+                board.unmake_move(&m, undo);
+            }
+        }
+    }
+
+    #[test]
+    fn test_knight_attacks_table() {
+        let board = Board::default();
+        let mg = MoveGen::new(&board);
+        for sq in 0..64 {
+            let knight = square_mask(Square::from_usize(sq));
+            assert!(KNIGHT_ATTACKS[sq] == mg.gen_knight_moves(knight));
+        }
+        assert!(
+            KNIGHT_ATTACKS[Square::A1 as usize]
+                == Bitboard::from_squares(&[Square::B3, Square::C2])
+        );
+    }
+
+    #[test]
+    fn test_king_attacks_table() {
+        for square in Square::iter() {
+            assert!(KING_ATTACKS[square as usize] == MoveGen::king_attacks(square_mask(square)));
+
+            // Fewer neighbours on the edges, 3 in the corners
+            let edges = [square.file(), square.rank()]
+                .into_iter()
+                .filter(|&c| c == 0 || c == 7)
+                .count();
+            assert_eq!(KING_ATTACKS[square as usize].count_ones(), [8, 5, 3][edges]);
+        }
+    }
+
+    #[test]
+    fn test_pawn_attacks_tables() {
+        let squares = Bitboard::from_squares;
+        for (pawn, white, mirror, black) in [
+            (
+                Square::A2,
+                squares(&[Square::B3]),
+                Square::A7,
+                squares(&[Square::B6]),
+            ),
+            (
+                Square::H2,
+                squares(&[Square::G3]),
+                Square::H7,
+                squares(&[Square::G6]),
+            ),
+            (
+                Square::D4,
+                squares(&[Square::C5, Square::E5]),
+                Square::D5,
+                squares(&[Square::C4, Square::E4]),
+            ),
+        ] {
+            assert!(pawn_attacks(Color::White, pawn) == white);
+            assert!(pawn_attacks(Color::Black, mirror) == black);
+        }
+        // No attacks from the last rank
+        assert!(WHITE_PAWN_ATTACKS[Square::E8 as usize] == 0);
+        assert!(BLACK_PAWN_ATTACKS[Square::E1 as usize] == 0);
+    }
+
+    #[test]
+    fn test_ordered_captures() {
+        // The black queen on d5 can be taken by the pawn, the knight and the
+        // queen, the rook on h8 by the bishop, and the pawn on a6 by the rook
+        let board = Board::from_fen("4k2r/8/p7/3qB3/4P3/2N5/8/R2QK3 w - - 0 1").unwrap();
+        let mut move_gen = MoveGen::new(&board);
+        move_gen.gen_capture_moves();
+        let order: Vec<String> = move_gen
+            .ordered_captures()
+            .iter()
+            .map(|m| m.to_uci())
+            .collect();
+        assert_eq!(order, ["e4d5", "c3d5", "d1d5", "e5h8", "a1a6"]);
+
+        // A promotion goes before capturing a rook
+        let board = Board::from_fen("4k2r/P7/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let mut move_gen = MoveGen::new(&board);
+        move_gen.gen_capture_moves();
+        let best = move_gen.ordered_captures()[0];
+        assert_eq!(best.promoting_piece, Some(Kind::Queen));
+    }
+
+    #[test]
+    fn test_reused_move_gen() {
+        let boards: Vec<Board> = PERFT_FENS
+            .iter()
+            .map(|fen| Board::from_fen(fen).unwrap())
+            .collect();
+        let mut mg = MoveGen::new(&boards[0]);
+        for board in &boards {
+            mg.set_board(board);
+            mg.gen_legal_moves();
+            let mut fresh = MoveGen::new(board);
+            fresh.gen_legal_moves();
+            assert_eq!(mg.get_legal_moves(), fresh.get_legal_moves());
+        }
+
+        // The pseudo moves are checked in place, so neither list is
+        // reallocated by the next generations
+        let capacity = mg.legal_move_list.capacity();
+        let pseudo_capacity = mg.pseudo_move_list.capacity();
+        assert!(pseudo_capacity > 0);
+        for board in &boards {
+            mg.set_board(board);
+            mg.gen_legal_moves();
+        }
+        assert_eq!(mg.pseudo_move_list.capacity(), pseudo_capacity);
+        mg.clear();
+        assert!(mg.get_legal_moves().is_empty());
+        assert!(mg.get_pseudo_moves().is_empty());
+        assert_eq!(mg.legal_move_list.capacity(), capacity);
+        assert_eq!(mg.pseudo_move_list.capacity(), pseudo_capacity);
+    }
+
+    #[test]
+    fn test_board_without_king() {
+        let board = Board::from_fen("8/8/8/3N4/8/8/8/8 w - - 0 1").unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_pseudo_moves();
+        assert_eq!(mg.get_pseudo_moves().len(), 8);
+        assert_eq!(board.mobility(Color::White), 8);
+        assert_eq!(board.mobility(Color::Black), 0);
+
+        // Nothing can be in check, so every pseudo legal move is legal
+        assert!(!board.is_in_check(Color::White));
+        assert!(board.checkers() == 0);
+        let mut mg = MoveGen::new(&board);
+        mg.gen_legal_moves();
+        assert_eq!(mg.get_legal_moves().len(), 8);
+        assert_eq!(board.legal_moves().count(), 8);
+        assert_eq!(board.count_legal_moves(), 8);
+        assert_eq!(board.legal_move_count(), 8);
+        assert_eq!(board.status(), crate::board::GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_squares_between() {
+        assert!(
+            squares_between(Square::E1, Square::E4)
+                == Bitboard::from_squares(&[Square::E2, Square::E3])
+        );
+        assert!(
+            squares_between(Square::B4, Square::E1)
+                == Bitboard::from_squares(&[Square::C3, Square::D2])
+        );
+        assert!(squares_between(Square::E1, Square::E2) == 0);
+        assert!(squares_between(Square::E1, Square::F3) == 0);
+    }
+
+    #[test]
+    fn test_gen_pseudo_to_mask() {
+        let center = Bitboard::from_squares(&[Square::D4, Square::E4, Square::D5, Square::E5]);
+        let board = Board::from_fen(PERFT_FENS[0]).unwrap();
+        let mut mg = MoveGen::new(&board);
+        mg.gen_pseudo_to_mask(center);
+        assert!(!mg.get_pseudo_moves().is_empty());
+        for m in mg.get_pseudo_moves() {
+            assert!(center & square_mask(m.to) != 0);
+        }
+
+        // Same moves as a full generation restricted to the center
+        let mut full = MoveGen::new(&board);
+        full.gen_pseudo_moves();
+        let expected = full
+            .get_pseudo_moves()
+            .iter()
+            .filter(|m| center & square_mask(m.to) != 0)
+            .count();
+        assert_eq!(mg.get_pseudo_moves().len(), expected);
+    }
+
+    #[test]
+    fn test_gen_captures() {
+        for fen in PERFT_FENS
+            .iter()
+            .chain(&["4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"])
+        {
+            let board = Board::from_fen(fen).unwrap();
+            let mut full = MoveGen::new(&board);
+            full.gen_pseudo_moves();
+            let expected: Vec<Move> = full
+                .get_pseudo_moves()
+                .iter()
+                .filter(|m| m.captured_piece.is_some())
+                .cloned()
+                .collect();
+            let mut mg = MoveGen::new(&board);
+            mg.gen_captures();
+            assert_eq!(mg.get_pseudo_moves(), &expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_gen_pseudo_to_mask_matches_filtering() {
+        let masks = [
+            MASK_RANK[0],
+            MASK_RANK[2] | MASK_RANK[5],
+            MASK_RANK[7],
+            CLEAR_FILE[4],
+        ];
+        for fen in PERFT_FENS {
+            let board = Board::from_fen(fen).unwrap();
+            let mut full = MoveGen::new(&board);
+            full.gen_pseudo_moves();
+            for targets in masks {
+                let mut mg = MoveGen::new(&board);
+                mg.gen_pseudo_to_mask(targets);
+                let expected: Vec<Move> = full
+                    .get_pseudo_moves()
+                    .iter()
+                    .filter(|m| {
+                        let taken = if m.en_passant {
+                            m.captured_square()
+                        } else {
+                            m.to
+                        };
+                        targets & (square_mask(m.to) | square_mask(taken)) != 0
+                    })
+                    .cloned()
+                    .collect();
+                assert_eq!(mg.get_pseudo_moves(), &expected, "{fen}");
+            }
+        }
+    }
+
     #[test]
     fn test_perft1() {
         let b = Board::default();
-        let p = perft(&b, 6);
+        let p = b.perft(6);
         assert_eq!(p, 119_060_324);
     }
     #[test]
@@ -1795,27 +2761,27 @@ mod tests {
         let b =
             Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ")
                 .unwrap();
-        let p = perft(&b, 5);
+        let p = b.perft(5);
         assert_eq!(p, 193_690_690);
     }
     #[test]
     fn test_perft3() {
         let b = Board::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
-        let p = perft(&b, 6);
+        let p = b.perft(6);
         assert_eq!(p, 11_030_083);
     }
     #[test]
     fn test_perft4() {
         let b = Board::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1")
             .unwrap();
-        let p = perft(&b, 6);
+        let p = b.perft(6);
         assert_eq!(p, 706_045_033);
     }
     #[test]
     fn test_perft5() {
         let b =
             Board::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
-        let p = perft(&b, 5);
+        let p = b.perft(5);
         assert_eq!(p, 89_941_194);
     }
     #[test]
@@ -1824,7 +2790,7 @@ mod tests {
             "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
         )
         .unwrap();
-        let p = perft(&b, 5);
+        let p = b.perft(5);
         assert_eq!(p, 164_075_551);
     }
 }
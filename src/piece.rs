@@ -1,7 +1,8 @@
 use crate::bitboard::Bitboard;
 use crate::utils::{Color, Kind};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Piece {
     pub kind: Kind,
     pub color: Color,
@@ -32,6 +33,16 @@ impl Piece {
         }
     }
 
+    /// The FEN letter of the piece: uppercase for White, lowercase for Black
+    pub fn get_fen_char(&self) -> char {
+        self.kind.to_fen_char(self.color)
+    }
+
+    /// ASCII rendering of the piece, for terminals and logs without chess glyphs
+    pub fn get_ascii_char(&self) -> char {
+        self.get_fen_char()
+    }
+
     pub fn get_char(&self) -> char {
         match (self.kind, self.color) {
             (Kind::King, Color::White) => '♔',
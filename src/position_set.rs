@@ -0,0 +1,65 @@
+//! This module provides a set of positions, used to detect transpositions
+//! while walking a game tree (e.g. when building an opening tree).
+use crate::board::Board;
+use rustc_hash::FxHashSet;
+
+#[derive(Default)]
+pub struct PositionSet {
+    keys: FxHashSet<u64>,
+}
+
+impl PositionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the position to the set.
+    /// Returns whether it was not already present.
+    pub fn insert_position(&mut self, board: &Board) -> bool {
+        self.keys.insert(board.repetition_key())
+    }
+
+    pub fn contains(&self, board: &Board) -> bool {
+        self.keys.contains(&board.repetition_key())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for uci in moves {
+            let m = board.parse_uci(uci).unwrap();
+            board.do_move(&m);
+        }
+    }
+
+    #[test]
+    fn test_transposition() {
+        let mut set = PositionSet::new();
+
+        // 1. Nf3 Nf6 2. Nc3
+        let mut a = Board::default();
+        play(&mut a, &["g1f3", "g8f6", "b1c3"]);
+        assert!(set.insert_position(&a));
+
+        // 1. Nc3 Nf6 2. Nf3
+        let mut b = Board::default();
+        play(&mut b, &["b1c3", "g8f6"]);
+        assert!(set.insert_position(&b));
+        play(&mut b, &["g1f3"]);
+        assert!(!set.insert_position(&b));
+
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&a));
+    }
+}
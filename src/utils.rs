@@ -2,9 +2,11 @@ use crate::bitboard::Bitboard;
 use crate::errors::ChessMgError;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Color {
     White,
     Black,
@@ -12,15 +14,29 @@ pub enum Color {
 
 impl Color {
     #[must_use]
-    pub fn opposite(&self) -> Self {
+    pub fn opposite(self) -> Self {
         match self {
             Color::White => Color::Black,
             Color::Black => Color::White,
         }
     }
+
+    /// The color as written in the active color field of a FEN
+    pub fn to_char(self) -> char {
+        match self {
+            Color::White => 'w',
+            Color::Black => 'b',
+        }
+    }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Kind {
     Pawn,
     Knight,
@@ -30,6 +46,57 @@ pub enum Kind {
     King,
 }
 
+impl Kind {
+    /// Material value of the piece, in centipawns.
+    /// The king is given no value, as it can never be traded.
+    pub fn value(self) -> i32 {
+        match self {
+            Kind::Pawn => 100,
+            Kind::Knight => 320,
+            Kind::Bishop => 330,
+            Kind::Rook => 500,
+            Kind::Queen => 900,
+            Kind::King => 0,
+        }
+    }
+
+    /// The kind and color of a FEN piece letter: uppercase for White,
+    /// lowercase for Black
+    pub fn from_char(c: char) -> Option<(Kind, Color)> {
+        let kind = match c.to_ascii_lowercase() {
+            'p' => Kind::Pawn,
+            'n' => Kind::Knight,
+            'b' => Kind::Bishop,
+            'r' => Kind::Rook,
+            'q' => Kind::Queen,
+            'k' => Kind::King,
+            _ => return None,
+        };
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some((kind, color))
+    }
+
+    /// The FEN letter of a piece of this kind, see `from_char`
+    pub fn to_fen_char(self, color: Color) -> char {
+        let c = match self {
+            Kind::Pawn => 'p',
+            Kind::Knight => 'n',
+            Kind::Bishop => 'b',
+            Kind::Rook => 'r',
+            Kind::Queen => 'q',
+            Kind::King => 'k',
+        };
+        match color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        }
+    }
+}
+
 // Te chosen layout is:
 //
 // 8 56 57 58 59 60 61 62 63
@@ -41,7 +108,7 @@ pub enum Kind {
 // 2 08 09 10 11 12 13 14 15
 // 1 00 01 02 03 04 05 06 07
 //   a  b  c  d  e  f  g  h
-#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq)]
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Square {
     A1 = 0,
     B1,
@@ -127,17 +194,6 @@ pub const MASK_RANK: [Bitboard; 8] = [
     Bitboard(0xFF << 56),
 ];
 
-pub const CLEAR_RANK: [Bitboard; 8] = [
-    Bitboard(0xFFFF_FFFF_FFFF_FF00),
-    Bitboard(0xFFFF_FFFF_FFFF_00FF),
-    Bitboard(0xFFFF_FFFF_FF00_FFFF),
-    Bitboard(0xFFFF_FFFF_00FF_FFFF),
-    Bitboard(0xFFFF_FF00_FFFF_FFFF),
-    Bitboard(0xFFFF_00FF_FFFF_FFFF),
-    Bitboard(0xFF00_FFFF_FFFF_FFFF),
-    Bitboard(0x00FF_FFFF_FFFF_FFFF),
-];
-
 pub const CLEAR_FILE: [Bitboard; 8] = [
     Bitboard(0xFEFE_FEFE_FEFE_FEFE),
     Bitboard(0xFDFD_FDFD_FDFD_FDFD),
@@ -768,6 +824,79 @@ impl FromStr for Square {
 }
 
 impl Square {
+    /// Every square, from a1 to h8 rank by rank
+    pub const ALL: [Square; 64] = [
+        Square::A1,
+        Square::B1,
+        Square::C1,
+        Square::D1,
+        Square::E1,
+        Square::F1,
+        Square::G1,
+        Square::H1,
+        Square::A2,
+        Square::B2,
+        Square::C2,
+        Square::D2,
+        Square::E2,
+        Square::F2,
+        Square::G2,
+        Square::H2,
+        Square::A3,
+        Square::B3,
+        Square::C3,
+        Square::D3,
+        Square::E3,
+        Square::F3,
+        Square::G3,
+        Square::H3,
+        Square::A4,
+        Square::B4,
+        Square::C4,
+        Square::D4,
+        Square::E4,
+        Square::F4,
+        Square::G4,
+        Square::H4,
+        Square::A5,
+        Square::B5,
+        Square::C5,
+        Square::D5,
+        Square::E5,
+        Square::F5,
+        Square::G5,
+        Square::H5,
+        Square::A6,
+        Square::B6,
+        Square::C6,
+        Square::D6,
+        Square::E6,
+        Square::F6,
+        Square::G6,
+        Square::H6,
+        Square::A7,
+        Square::B7,
+        Square::C7,
+        Square::D7,
+        Square::E7,
+        Square::F7,
+        Square::G7,
+        Square::H7,
+        Square::A8,
+        Square::B8,
+        Square::C8,
+        Square::D8,
+        Square::E8,
+        Square::F8,
+        Square::G8,
+        Square::H8,
+    ];
+
+    /// Iterates over every square, from a1 to h8, see `ALL`
+    pub fn iter() -> impl Iterator<Item = Square> {
+        Self::ALL.into_iter()
+    }
+
     pub fn from_u8(integer: u8) -> Self {
         match FromPrimitive::from_u8(integer) {
             Some(square) => square,
@@ -851,14 +980,59 @@ impl Square {
         }
     }
 
+    /// The square on `rank` and `file`, both from 0 to 7
+    pub fn from_rank_file(rank: u8, file: u8) -> Self {
+        debug_assert!(rank < 8 && file < 8);
+        Square::from_u8(rank * 8 + file)
+    }
+
+    /// The rank of the square, from 0 (rank 1) to 7 (rank 8)
+    pub fn rank(self) -> u8 {
+        self as u8 / 8 // relies on enum order A1=0,...H8=63
+    }
+
+    /// The file of the square, from 0 (file a) to 7 (file h)
+    pub fn file(self) -> u8 {
+        self as u8 % 8
+    }
+
     pub fn to_coords(self) -> (u8, u8) {
-        let idx = self as u8; // relies on enum order A1=0,...H8=63
-        (idx % 8, idx / 8)
+        (self.file(), self.rank())
+    }
+
+    /// The file of the square, from 'a' to 'h'
+    pub fn file_char(self) -> char {
+        (b'a' + self.file()) as char
+    }
+
+    /// The rank of the square, from '1' to '8'
+    pub fn rank_char(self) -> char {
+        (b'1' + self.rank()) as char
+    }
+
+    /// The square mirrored across the middle of the board between the
+    /// 4th and 5th ranks: a1 <-> a8, e2 <-> e7
+    #[must_use]
+    pub fn flip_vertical(self) -> Square {
+        Square::from_u8(self as u8 ^ 0b11_1000)
+    }
+
+    /// The square mirrored across the middle of the board between the
+    /// d and e files: a1 <-> h1, d4 <-> e4
+    #[must_use]
+    pub fn flip_horizontal(self) -> Square {
+        Square::from_u8(self as u8 ^ 0b111)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.square_to_str())
     }
 }
 
 #[allow(clippy::struct_excessive_bools, reason = "I now what I do")]
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Casteling {
     // This truct tells whether the king of a given color can
     // castle:
@@ -881,6 +1055,138 @@ impl Default for Casteling {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum CastleSide {
+    King,
+    Queen,
+}
+
+impl Casteling {
+    pub fn get(&self, color: Color, side: CastleSide) -> bool {
+        match (color, side) {
+            (Color::White, CastleSide::King) => self.white_kingside,
+            (Color::White, CastleSide::Queen) => self.white_queenside,
+            (Color::Black, CastleSide::King) => self.black_kingside,
+            (Color::Black, CastleSide::Queen) => self.black_queenside,
+        }
+    }
+
+    pub fn set(&mut self, color: Color, side: CastleSide, value: bool) {
+        let right = match (color, side) {
+            (Color::White, CastleSide::King) => &mut self.white_kingside,
+            (Color::White, CastleSide::Queen) => &mut self.white_queenside,
+            (Color::Black, CastleSide::King) => &mut self.black_kingside,
+            (Color::Black, CastleSide::Queen) => &mut self.black_queenside,
+        };
+        *right = value;
+    }
+}
+
 pub fn square_mask(square: Square) -> Bitboard {
     Bitboard(1 << square as u8)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_chars() {
+        let kinds = [
+            (Kind::Pawn, 'P', 100),
+            (Kind::Knight, 'N', 320),
+            (Kind::Bishop, 'B', 330),
+            (Kind::Rook, 'R', 500),
+            (Kind::Queen, 'Q', 900),
+            (Kind::King, 'K', 0),
+        ];
+        for (kind, c, value) in kinds {
+            let lower = c.to_ascii_lowercase();
+            assert_eq!(kind.to_fen_char(Color::White), c);
+            assert_eq!(kind.to_fen_char(Color::Black), lower);
+            assert_eq!(Kind::from_char(c), Some((kind, Color::White)));
+            assert_eq!(Kind::from_char(lower), Some((kind, Color::Black)));
+            assert_eq!(kind.value(), value);
+        }
+        assert_eq!(Kind::from_char('x'), None);
+        assert_eq!(Kind::from_char('1'), None);
+    }
+
+    #[test]
+    fn test_color_opposite_and_char() {
+        assert_eq!(Color::White.opposite(), Color::Black);
+        assert_eq!(Color::Black.opposite(), Color::White);
+        assert_eq!(Color::White.to_char(), 'w');
+        assert_eq!(Color::Black.to_char(), 'b');
+        assert_eq!(Color::Black.to_string(), "b");
+    }
+
+    #[test]
+    fn test_square_rank_file() {
+        for (square, rank, file) in [
+            (Square::A1, 0, 0),
+            (Square::H1, 0, 7),
+            (Square::A8, 7, 0),
+            (Square::H8, 7, 7),
+            (Square::E4, 3, 4),
+            (Square::C6, 5, 2),
+        ] {
+            assert_eq!(square.rank(), rank);
+            assert_eq!(square.file(), file);
+            assert_eq!(Square::from_rank_file(rank, file), square);
+        }
+    }
+
+    #[test]
+    fn test_square_display() {
+        assert_eq!(Square::E4.to_string(), "e4");
+        assert_eq!(format!("{}{}", Square::A1, Square::H8), "a1h8");
+        assert_eq!(Square::E4.file_char(), 'e');
+        assert_eq!(Square::E4.rank_char(), '4');
+        for square in Square::iter() {
+            assert_eq!(Square::from_str(&square.to_string()).unwrap(), square);
+        }
+    }
+
+    #[test]
+    fn test_flip_square() {
+        assert_eq!(Square::A1.flip_vertical(), Square::A8);
+        assert_eq!(Square::A1.flip_horizontal(), Square::H1);
+        assert_eq!(Square::E2.flip_vertical(), Square::E7);
+        assert_eq!(Square::D4.flip_horizontal(), Square::E4);
+        for square in Square::iter() {
+            assert_eq!(square.flip_vertical().flip_vertical(), square);
+            assert_eq!(square.flip_horizontal().flip_horizontal(), square);
+            assert_eq!(square.flip_vertical().file(), square.file());
+            assert_eq!(square.flip_vertical().rank(), 7 - square.rank());
+            assert_eq!(square.flip_horizontal().rank(), square.rank());
+            assert_eq!(square.flip_horizontal().file(), 7 - square.file());
+        }
+    }
+
+    #[test]
+    fn test_all_squares() {
+        assert_eq!(Square::ALL.len(), 64);
+        assert_eq!(Square::ALL[0], Square::A1);
+        assert_eq!(Square::ALL[63], Square::H8);
+        for (i, square) in Square::iter().enumerate() {
+            assert_eq!(square as usize, i);
+        }
+    }
+
+    #[test]
+    fn test_casteling_accessors() {
+        let mut rights = Casteling::default();
+        assert!(rights.get(Color::White, CastleSide::King));
+        assert!(rights.get(Color::Black, CastleSide::Queen));
+
+        rights.set(Color::Black, CastleSide::Queen, false);
+        assert!(!rights.get(Color::Black, CastleSide::Queen));
+        assert!(!rights.black_queenside);
+        assert!(rights.get(Color::Black, CastleSide::King));
+        assert!(rights.get(Color::White, CastleSide::Queen));
+
+        rights.set(Color::Black, CastleSide::Queen, true);
+        assert!(rights.black_queenside);
+    }
+}
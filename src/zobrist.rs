@@ -0,0 +1,55 @@
+//! This module provides the random keys used to compute the Zobrist hash
+//! of a position.
+//! Each (piece, square) pair, castling right, en passant file and the side
+//! to move gets its own key, and the hash of a position is the XOR of the keys
+//! of everything present in it. This makes it cheap to update the hash
+//! when a move is played, as XOR is its own inverse.
+use crate::utils::{Color, Kind, Square};
+use std::array::from_fn;
+use std::sync::LazyLock;
+
+pub struct ZobristKeys {
+    /// One key per piece (6 kinds for 2 colors) and square
+    pub pieces: [[u64; 64]; 12],
+
+    /// One key per castling right, in the order
+    /// white kingside, white queenside, black kingside, black queenside
+    pub castling: [u64; 4],
+
+    /// One key per file, used when an en passant square is set
+    pub en_passant: [u64; 8],
+
+    /// Present in the hash when Black is to move
+    pub black_to_move: u64,
+}
+
+impl ZobristKeys {
+    pub fn piece(&self, kind: Kind, color: Color, square: Square) -> u64 {
+        let index = kind as usize
+            + match color {
+                Color::White => 0,
+                Color::Black => 6,
+            };
+        self.pieces[index][square as usize]
+    }
+}
+
+/// `SplitMix64`, a small PRNG, good enough to generate the keys.
+/// It is seeded with a constant so that hashes are stable across runs.
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub static ZOBRIST: LazyLock<ZobristKeys> = LazyLock::new(|| {
+    let mut state = 0x00C0_FFEE_u64;
+    ZobristKeys {
+        pieces: from_fn(|_| from_fn(|_| split_mix(&mut state))),
+        castling: from_fn(|_| split_mix(&mut state)),
+        en_passant: from_fn(|_| split_mix(&mut state)),
+        black_to_move: split_mix(&mut state),
+    }
+});
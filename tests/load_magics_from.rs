@@ -0,0 +1,25 @@
+//! `load_magics_from` sets where the magic tables of the whole process are
+//! read and written, so it is tested in its own process.
+use chessmg::{load_magics_from, Board};
+use std::fs;
+
+#[test]
+fn test_load_magics_from() {
+    let dir = std::env::temp_dir().join(format!("chessmg-load-from-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    // Nothing was loaded yet, so the directory is taken into account
+    assert!(load_magics_from(&dir));
+    assert!(dir.join("rook_magics_v2.bin").exists());
+    assert!(dir.join("bishop_magics_v2.bin").exists());
+
+    // Once loaded, the tables stay where they are
+    let other = dir.join("other");
+    assert!(!load_magics_from(&other));
+    assert!(!other.exists());
+
+    // The tables in use are the ones written there
+    assert_eq!(Board::default().perft(3), 8_902);
+
+    fs::remove_dir_all(&dir).unwrap();
+}